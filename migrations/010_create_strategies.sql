@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS strategies (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    note TEXT NOT NULL DEFAULT '',
+    options TEXT NOT NULL DEFAULT '{}',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    modified_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS strategy_assignments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    strategy_id INTEGER NOT NULL REFERENCES strategies(id) ON DELETE CASCADE,
+    device_id INTEGER REFERENCES devices(id) ON DELETE CASCADE,
+    user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    device_group_id INTEGER REFERENCES device_groups(id) ON DELETE CASCADE,
+    modified_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+    CHECK ((device_id IS NOT NULL) + (user_id IS NOT NULL) + (device_group_id IS NOT NULL) = 1)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_strategy_assignments_device
+    ON strategy_assignments(device_id) WHERE device_id IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_strategy_assignments_user
+    ON strategy_assignments(user_id) WHERE user_id IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_strategy_assignments_device_group
+    ON strategy_assignments(device_group_id) WHERE device_group_id IS NOT NULL;
+
+ALTER TABLE devices ADD COLUMN strategy_version INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN strategy_source INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN strategy_options TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS device_sysinfo_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    device_id INTEGER NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    hostname TEXT NOT NULL DEFAULT '',
+    os TEXT NOT NULL DEFAULT '',
+    cpu TEXT NOT NULL DEFAULT '',
+    memory TEXT NOT NULL DEFAULT '',
+    version TEXT NOT NULL DEFAULT '',
+    recorded_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_device_sysinfo_history_device ON device_sysinfo_history(device_id, id);
+
+INSERT INTO device_sysinfo_history (device_id, hostname, os, cpu, memory, version, recorded_at)
+SELECT id, hostname, os, cpu, memory, version, last_online FROM devices
+WHERE hostname != '' OR os != '' OR version != ''
@@ -0,0 +1,3 @@
+ALTER TABLE devices ADD COLUMN status TEXT NOT NULL DEFAULT 'approved';
+
+CREATE INDEX IF NOT EXISTS idx_devices_status ON devices(status)
@@ -0,0 +1 @@
+ALTER TABLE devices ADD COLUMN online BOOLEAN NOT NULL DEFAULT FALSE
@@ -0,0 +1,19 @@
+ALTER TABLE address_books ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE address_books ADD COLUMN modified_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE peers ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE tags ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS ab_tombstones (
+    ab_guid TEXT NOT NULL REFERENCES address_books(guid) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    revision INTEGER NOT NULL,
+    PRIMARY KEY (ab_guid, kind, name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_peers_revision ON peers(ab_guid, revision);
+CREATE INDEX IF NOT EXISTS idx_tags_revision ON tags(ab_guid, revision);
+
+UPDATE address_books SET revision = 1;
+UPDATE peers SET revision = 1;
+UPDATE tags SET revision = 1
@@ -0,0 +1,5 @@
+ALTER TABLE peers ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE tags ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
+
+UPDATE peers SET version = 1;
+UPDATE tags SET version = 1
@@ -0,0 +1,14 @@
+CREATE VIRTUAL TABLE IF NOT EXISTS peer_search USING fts5(
+    rustdesk_id,
+    alias,
+    hostname,
+    username,
+    note,
+    tags,
+    tokenize = 'unicode61'
+);
+
+INSERT INTO peer_search (rowid, rustdesk_id, alias, hostname, username, note, tags)
+SELECT p.id, p.rustdesk_id, p.alias, p.hostname, p.username, p.note,
+       COALESCE((SELECT group_concat(t.name, ' ') FROM peer_tags pt JOIN tags t ON t.id = pt.tag_id WHERE pt.peer_id = p.id), '')
+FROM peers p
@@ -0,0 +1,3 @@
+ALTER TABLE peers ADD COLUMN deleted_at DATETIME;
+
+CREATE INDEX IF NOT EXISTS idx_peers_deleted ON peers(ab_guid, deleted_at)
@@ -0,0 +1,34 @@
+CREATE TABLE IF NOT EXISTS ab_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ab_guid TEXT NOT NULL REFERENCES address_books(guid) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    row_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    source TEXT NOT NULL DEFAULT '',
+    revision INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_ab_history_book ON ab_history(ab_guid, id);
+CREATE INDEX IF NOT EXISTS idx_ab_history_row ON ab_history(kind, row_id, id);
+
+INSERT INTO ab_history (ab_guid, kind, row_id, name, action, new_value, source, revision)
+SELECT p.ab_guid, 'peer', p.id, p.rustdesk_id, 'create',
+       json_object(
+           'id', p.rustdesk_id, 'username', p.username, 'hostname', p.hostname,
+           'platform', p.platform, 'alias', p.alias, 'note', p.note,
+           'tags', json(COALESCE((SELECT json_group_array(name) FROM (
+               SELECT t.name FROM peer_tags pt JOIN tags t ON t.id = pt.tag_id
+               WHERE pt.peer_id = p.id ORDER BY t.name
+           )), '[]'))
+       ),
+       'migration', p.revision
+FROM peers p WHERE p.deleted_at IS NULL;
+
+INSERT INTO ab_history (ab_guid, kind, row_id, name, action, new_value, source, revision)
+SELECT ab_guid, 'tag', id, name, 'create', json_object('name', name, 'color', color), 'migration', revision
+FROM tags
@@ -0,0 +1 @@
+ALTER TABLE address_books ADD COLUMN max_peers INTEGER
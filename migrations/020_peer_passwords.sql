@@ -0,0 +1 @@
+ALTER TABLE peers ADD COLUMN password TEXT NOT NULL DEFAULT ''
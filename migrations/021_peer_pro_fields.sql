@@ -0,0 +1,7 @@
+ALTER TABLE peers ADD COLUMN force_always_relay BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE peers ADD COLUMN rdp_port TEXT NOT NULL DEFAULT '';
+ALTER TABLE peers ADD COLUMN rdp_username TEXT NOT NULL DEFAULT '';
+ALTER TABLE peers ADD COLUMN login_name TEXT NOT NULL DEFAULT '';
+ALTER TABLE peers ADD COLUMN device_group_name TEXT NOT NULL DEFAULT '';
+ALTER TABLE peers ADD COLUMN same_server BOOLEAN;
+ALTER TABLE peers ADD COLUMN extra TEXT NOT NULL DEFAULT '{}'
@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS ab_custom_fields (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ab_guid TEXT NOT NULL REFERENCES address_books(guid) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    field_type TEXT NOT NULL,
+    options TEXT NOT NULL DEFAULT '[]',
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(ab_guid, name)
+);
+
+ALTER TABLE peers ADD COLUMN custom_fields TEXT NOT NULL DEFAULT '{}';
+
+DROP TABLE IF EXISTS peer_search;
+
+CREATE VIRTUAL TABLE peer_search USING fts5(
+    rustdesk_id,
+    alias,
+    hostname,
+    username,
+    note,
+    tags,
+    custom_fields,
+    tokenize = 'unicode61'
+);
+
+INSERT INTO peer_search (rowid, rustdesk_id, alias, hostname, username, note, tags, custom_fields)
+SELECT p.id, p.rustdesk_id, p.alias, p.hostname, p.username, p.note,
+       COALESCE((SELECT group_concat(t.name, ' ') FROM peer_tags pt JOIN tags t ON t.id = pt.tag_id WHERE pt.peer_id = p.id), ''),
+       ''
+FROM peers p WHERE p.deleted_at IS NULL
@@ -1,5 +1,5 @@
 use axum::{
-    extract::FromRequestParts,
+    extract::{FromRequestParts, OptionalFromRequestParts},
     http::request::Parts,
 };
 
@@ -33,3 +33,18 @@ impl FromRequestParts<AppState> for AuthUser {
         Ok(AuthUser(claims))
     }
 }
+
+/// `Option<AuthUser>` for endpoints that also accept anonymous clients
+/// (heartbeat, sysinfo). A missing or invalid token yields `None`.
+impl OptionalFromRequestParts<AppState> for AuthUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(<AuthUser as FromRequestParts<AppState>>::from_request_parts(parts, state)
+            .await
+            .ok())
+    }
+}
@@ -1,3 +1,4 @@
 pub mod jwt;
 pub mod middleware;
 pub mod password;
+pub mod peer_password;
@@ -0,0 +1,198 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::error::ApiError;
+
+/// Keys for encrypting stored peer passwords (AES-256-GCM), parsed from the
+/// `peer_password_keys` config as `id:key` entries, where the key is 32 random
+/// bytes in base64. The first key encrypts new values; the rest are only used
+/// to read values written before a rotation.
+///
+/// Stored values look like `v1:<key id>:<base64 of nonce + ciphertext>`. The
+/// ciphertext is bound to its peer (book and RustDesk ID), so it can't be
+/// copied to another row; see `reseal` for peers that move.
+#[derive(Clone, Default)]
+pub struct PeerPasswordKeys {
+    keys: Vec<(String, Aes256Gcm)>,
+}
+
+impl PeerPasswordKeys {
+    pub fn new(entries: &[String]) -> Result<Self, String> {
+        let mut keys: Vec<(String, Aes256Gcm)> = Vec::new();
+        for entry in entries {
+            let Some((id, key)) = entry.split_once(':') else {
+                return Err("peer password keys must look like id:key".to_string());
+            };
+            if id.is_empty() {
+                return Err("peer password key id cannot be empty".to_string());
+            }
+            if keys.iter().any(|(existing, _)| existing == id) {
+                return Err(format!("duplicate peer password key id '{}'", id));
+            }
+            let key = STANDARD.decode(key).ok().filter(|key| key.len() == 32).ok_or_else(|| {
+                format!(
+                    "peer password key '{}' must be 32 bytes in base64 (openssl rand -base64 32)",
+                    id
+                )
+            })?;
+            let cipher = Aes256Gcm::new_from_slice(&key).map_err(|e| e.to_string())?;
+            keys.push((id.to_string(), cipher));
+        }
+        Ok(PeerPasswordKeys { keys })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Encrypt the password of peer `rustdesk_id` in book `ab_guid` with the
+    /// active key. An empty password stays empty.
+    pub fn encrypt(
+        &self,
+        plaintext: &str,
+        ab_guid: &str,
+        rustdesk_id: &str,
+    ) -> Result<String, ApiError> {
+        if plaintext.is_empty() {
+            return Ok(String::new());
+        }
+        let Some((id, cipher)) = self.keys.first() else {
+            return Err(ApiError::BadRequest(
+                "Storing peer passwords is not enabled on this server".to_string(),
+            ));
+        };
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: &associated_data(ab_guid, rustdesk_id),
+                },
+            )
+            .map_err(|_| ApiError::Internal("Failed to encrypt peer password".to_string()))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(format!("v1:{}:{}", id, STANDARD.encode(sealed)))
+    }
+
+    /// Decrypt the stored password of peer `rustdesk_id` in book `ab_guid`.
+    /// `None` if it was written with a key that is no longer configured, or
+    /// doesn't authenticate (including when it belongs to another peer).
+    pub fn decrypt(&self, stored: &str, ab_guid: &str, rustdesk_id: &str) -> Option<String> {
+        if stored.is_empty() {
+            return Some(String::new());
+        }
+        let mut parts = stored.splitn(3, ':');
+        let (Some("v1"), Some(id), Some(data)) = (parts.next(), parts.next(), parts.next()) else {
+            return None;
+        };
+        let (_, cipher) = self.keys.iter().find(|(key_id, _)| key_id == id)?;
+
+        let sealed = STANDARD.decode(data).ok()?;
+        if sealed.len() < 12 {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(12);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &associated_data(ab_guid, rustdesk_id),
+        };
+        let plaintext = cipher.decrypt(Nonce::from_slice(nonce), payload).ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+
+    /// Re-encrypt a stored password for the peer it is copied to, given as
+    /// `(ab_guid, rustdesk_id)` like the peer it comes from. A password that
+    /// can't be decrypted any more is dropped.
+    pub fn reseal(
+        &self,
+        stored: &str,
+        from: (&str, &str),
+        to: (&str, &str),
+    ) -> Result<String, ApiError> {
+        if stored.is_empty() || from == to {
+            return Ok(stored.to_string());
+        }
+        match self.decrypt(stored, from.0, from.1) {
+            Some(plaintext) => self.encrypt(&plaintext, to.0, to.1),
+            None => {
+                tracing::warn!("Dropping a saved peer password that could not be decrypted");
+                Ok(String::new())
+            }
+        }
+    }
+
+    /// Whether a stored value is empty or already encrypted with the active key.
+    pub fn is_current(&self, stored: &str) -> bool {
+        match self.keys.first() {
+            _ if stored.is_empty() => true,
+            Some((id, _)) => stored.starts_with(&format!("v1:{}:", id)),
+            None => false,
+        }
+    }
+}
+
+/// What a peer's ciphertext is bound to.
+fn associated_data(ab_guid: &str, rustdesk_id: &str) -> Vec<u8> {
+    format!("{}\0{}", ab_guid, rustdesk_id).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K1: &str = "k1:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+    const K2: &str = "k2:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+    fn keys(entries: &[&str]) -> PeerPasswordKeys {
+        let entries: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+        PeerPasswordKeys::new(&entries).unwrap()
+    }
+
+    #[test]
+    fn passwords_round_trip_for_their_own_peer_only() {
+        let keys = keys(&[K1]);
+        let stored = keys.encrypt("secret", "book", "100").unwrap();
+        assert!(stored.starts_with("v1:k1:"));
+        assert!(!stored.contains("secret"));
+        assert_eq!(keys.decrypt(&stored, "book", "100").as_deref(), Some("secret"));
+
+        // Swapped into another row the ciphertext no longer authenticates
+        assert_eq!(keys.decrypt(&stored, "book", "200"), None);
+        assert_eq!(keys.decrypt(&stored, "other", "100"), None);
+
+        let moved = keys.reseal(&stored, ("book", "100"), ("other", "100")).unwrap();
+        assert_eq!(keys.decrypt(&moved, "other", "100").as_deref(), Some("secret"));
+
+        assert_eq!(keys.encrypt("", "book", "100").unwrap(), "");
+    }
+
+    #[test]
+    fn older_keys_still_decrypt_after_a_rotation() {
+        let stored = keys(&[K1]).encrypt("secret", "book", "100").unwrap();
+
+        let rotated = keys(&[K2, K1]);
+        assert!(!rotated.is_current(&stored));
+        assert_eq!(rotated.decrypt(&stored, "book", "100").as_deref(), Some("secret"));
+        let reencrypted = rotated.encrypt("secret", "book", "100").unwrap();
+        assert!(rotated.is_current(&reencrypted));
+
+        // Once the old key is gone, its values can't be read
+        assert_eq!(keys(&[K2]).decrypt(&stored, "book", "100"), None);
+        assert_eq!(keys(&[K2]).decrypt("v1:k9:AAAA", "book", "100"), None);
+    }
+
+    #[test]
+    fn keys_must_be_32_bytes_of_base64() {
+        for entry in ["k1:secret", "k1:AAAA", ":AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=", "k1"] {
+            assert!(PeerPasswordKeys::new(&[entry.to_string()]).is_err(), "{}", entry);
+        }
+        assert!(PeerPasswordKeys::new(&[K1.to_string(), K1.to_string()]).is_err());
+    }
+}
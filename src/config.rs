@@ -15,6 +15,40 @@ pub struct Config {
     pub admin_password: String,
     #[serde(default = "default_token_expiry_hours")]
     pub token_expiry_hours: u64,
+    /// New devices start out pending until an admin approves them.
+    #[serde(default)]
+    pub device_approval: bool,
+    /// If set, unknown devices must present this token to enroll.
+    #[serde(default)]
+    pub enrollment_token: String,
+    /// Seconds without a heartbeat after which a device is marked offline.
+    #[serde(default = "default_offline_after_secs")]
+    pub offline_after_secs: u64,
+    /// Delete devices unseen for this many days (0 = never).
+    #[serde(default)]
+    pub prune_devices_after_days: u64,
+    /// Address book tag that marks a device as critical.
+    #[serde(default = "default_critical_tag")]
+    pub critical_tag: String,
+    /// Shell command run when a critical device goes offline.
+    #[serde(default)]
+    pub offline_hook: String,
+    /// Days deleted peers stay in the trash before being purged (0 = never).
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u64,
+    /// Most live peers an address book may hold (0 = unlimited). Books can
+    /// override this individually.
+    #[serde(default)]
+    pub max_peers_per_ab: u64,
+    /// Keys for encrypting stored peer passwords as `id:key`, the key being 32
+    /// bytes in base64; the first one encrypts, the others can still decrypt.
+    /// Empty disables peer passwords.
+    #[serde(default)]
+    pub peer_password_keys: Vec<String>,
+    /// hbbs's `db_v2.sqlite3`, read to import registered IDs. Empty disables
+    /// the hbbs import.
+    #[serde(default)]
+    pub hbbs_db_path: String,
 }
 
 fn default_port() -> u16 {
@@ -42,6 +76,15 @@ fn default_admin_password() -> String {
 fn default_token_expiry_hours() -> u64 {
     168 // 7 days
 }
+fn default_offline_after_secs() -> u64 {
+    120
+}
+fn default_critical_tag() -> String {
+    "critical".to_string()
+}
+fn default_trash_retention_days() -> u64 {
+    30
+}
 
 impl Config {
     pub fn load() -> Self {
@@ -71,6 +114,42 @@ impl Config {
         if let Ok(v) = std::env::var("RUSTDESK_AB_ADMIN_PASSWORD") {
             config.admin_password = v;
         }
+        if let Ok(v) = std::env::var("RUSTDESK_AB_DEVICE_APPROVAL") {
+            config.device_approval = v.parse().expect("Invalid RUSTDESK_AB_DEVICE_APPROVAL");
+        }
+        if let Ok(v) = std::env::var("RUSTDESK_AB_ENROLLMENT_TOKEN") {
+            config.enrollment_token = v;
+        }
+        if let Ok(v) = std::env::var("RUSTDESK_AB_OFFLINE_AFTER_SECS") {
+            config.offline_after_secs = v.parse().expect("Invalid RUSTDESK_AB_OFFLINE_AFTER_SECS");
+        }
+        if let Ok(v) = std::env::var("RUSTDESK_AB_PRUNE_DEVICES_AFTER_DAYS") {
+            config.prune_devices_after_days =
+                v.parse().expect("Invalid RUSTDESK_AB_PRUNE_DEVICES_AFTER_DAYS");
+        }
+        if let Ok(v) = std::env::var("RUSTDESK_AB_CRITICAL_TAG") {
+            config.critical_tag = v;
+        }
+        if let Ok(v) = std::env::var("RUSTDESK_AB_OFFLINE_HOOK") {
+            config.offline_hook = v;
+        }
+        if let Ok(v) = std::env::var("RUSTDESK_AB_TRASH_RETENTION_DAYS") {
+            config.trash_retention_days =
+                v.parse().expect("Invalid RUSTDESK_AB_TRASH_RETENTION_DAYS");
+        }
+        if let Ok(v) = std::env::var("RUSTDESK_AB_MAX_PEERS_PER_AB") {
+            config.max_peers_per_ab = v.parse().expect("Invalid RUSTDESK_AB_MAX_PEERS_PER_AB");
+        }
+        if let Ok(v) = std::env::var("RUSTDESK_AB_PEER_PASSWORD_KEYS") {
+            config.peer_password_keys = v
+                .split(',')
+                .map(|k| k.trim().to_string())
+                .filter(|k| !k.is_empty())
+                .collect();
+        }
+        if let Ok(v) = std::env::var("RUSTDESK_AB_HBBS_DB_PATH") {
+            config.hbbs_db_path = v;
+        }
 
         config
     }
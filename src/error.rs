@@ -1,6 +1,6 @@
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use serde_json::json;
+use serde_json::{json, Value};
 
 #[derive(Debug)]
 pub enum ApiError {
@@ -8,7 +8,9 @@ pub enum ApiError {
     Unauthorized(String),
     Forbidden(String),
     NotFound(String),
-    Conflict(String),
+    /// Optionally carries the current state of the conflicting resource.
+    Conflict(String, Option<Value>),
+    PreconditionFailed(String),
     Internal(String),
 }
 
@@ -19,7 +21,8 @@ impl std::fmt::Display for ApiError {
             ApiError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
             ApiError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
             ApiError::NotFound(msg) => write!(f, "Not found: {}", msg),
-            ApiError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            ApiError::Conflict(msg, _) => write!(f, "Conflict: {}", msg),
+            ApiError::PreconditionFailed(msg) => write!(f, "Precondition failed: {}", msg),
             ApiError::Internal(msg) => write!(f, "Internal error: {}", msg),
         }
     }
@@ -27,12 +30,18 @@ impl std::fmt::Display for ApiError {
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
+        if let ApiError::Conflict(msg, Some(current)) = &self {
+            let body = json!({ "error": msg, "current": current });
+            return (StatusCode::CONFLICT, axum::Json(body)).into_response();
+        }
+
         let (status, error_message) = match &self {
             ApiError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
             ApiError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg.clone()),
             ApiError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg.clone()),
             ApiError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
-            ApiError::Conflict(msg) => (StatusCode::CONFLICT, msg.clone()),
+            ApiError::Conflict(msg, _) => (StatusCode::CONFLICT, msg.clone()),
+            ApiError::PreconditionFailed(msg) => (StatusCode::PRECONDITION_FAILED, msg.clone()),
             ApiError::Internal(msg) => {
                 tracing::error!("Internal server error: {}", msg);
                 (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string())
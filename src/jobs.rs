@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use crate::routes::trash::purge_peers;
+use crate::state::AppState;
+
+/// How often the device monitor runs.
+const MONITOR_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often expired peers are purged from the trash.
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Background task: marks devices offline once their heartbeats stop, alerts
+/// on critical devices going offline and prunes long-unseen devices.
+pub async fn run_device_monitor(state: AppState) {
+    let mut interval = tokio::time::interval(MONITOR_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = mark_offline(&state).await {
+            tracing::error!("Device monitor failed to mark offline devices: {}", e);
+        }
+        if state.config.prune_devices_after_days > 0 {
+            if let Err(e) = prune_devices(&state).await {
+                tracing::error!("Device monitor failed to prune devices: {}", e);
+            }
+        }
+    }
+}
+
+async fn mark_offline(state: &AppState) -> Result<(), sqlx::Error> {
+    let stale: Vec<(i64, String, String, String, bool)> = sqlx::query_as(
+        "SELECT d.id, d.rustdesk_id, d.hostname, d.last_online, EXISTS (
+             SELECT 1 FROM peers p
+             JOIN peer_tags pt ON pt.peer_id = p.id
+             JOIN tags t ON t.id = pt.tag_id
+             WHERE p.rustdesk_id = d.rustdesk_id AND p.deleted_at IS NULL AND t.name = ?
+         )
+         FROM devices d
+         WHERE d.online AND d.last_online < datetime('now', ?)",
+    )
+    .bind(&state.config.critical_tag)
+    .bind(format!("-{} seconds", state.config.offline_after_secs))
+    .fetch_all(&state.db)
+    .await?;
+
+    for (id, rustdesk_id, hostname, last_online, critical) in stale {
+        sqlx::query("UPDATE devices SET online = FALSE WHERE id = ?")
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+
+        if critical {
+            tracing::warn!("Critical device {} ({}) went offline", rustdesk_id, hostname);
+
+            sqlx::query(
+                "INSERT INTO audit_log (action, rustdesk_id, note) VALUES ('device_offline', ?, ?)",
+            )
+            .bind(&rustdesk_id)
+            .bind(format!("Last seen {}", last_online))
+            .execute(&state.db)
+            .await?;
+
+            run_offline_hook(state, &rustdesk_id, &hostname, &last_online);
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the configured notification command without waiting for it to finish.
+fn run_offline_hook(state: &AppState, rustdesk_id: &str, hostname: &str, last_online: &str) {
+    if state.config.offline_hook.is_empty() {
+        return;
+    }
+
+    let child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(&state.config.offline_hook)
+        .env("RUSTDESK_ID", rustdesk_id)
+        .env("HOSTNAME", hostname)
+        .env("LAST_ONLINE", last_online)
+        .spawn();
+
+    match child {
+        Ok(mut child) => {
+            let rustdesk_id = rustdesk_id.to_string();
+            tokio::spawn(async move {
+                match child.wait().await {
+                    Ok(status) if !status.success() => {
+                        tracing::warn!("Offline hook for {} exited with {}", rustdesk_id, status)
+                    }
+                    Err(e) => tracing::warn!("Offline hook for {} failed: {}", rustdesk_id, e),
+                    _ => {}
+                }
+            });
+        }
+        Err(e) => tracing::warn!("Failed to run offline hook: {}", e),
+    }
+}
+
+async fn prune_devices(state: &AppState) -> Result<(), sqlx::Error> {
+    let cutoff = format!("-{} days", state.config.prune_devices_after_days);
+
+    // Remove dependent rows first, then the devices themselves
+    for table in ["device_commands", "strategy_assignments", "device_sysinfo_history"] {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE device_id IN (SELECT id FROM devices WHERE last_online < datetime('now', ?))",
+            table
+        ))
+        .bind(&cutoff)
+        .execute(&state.db)
+        .await?;
+    }
+
+    let result = sqlx::query("DELETE FROM devices WHERE last_online < datetime('now', ?)")
+        .bind(&cutoff)
+        .execute(&state.db)
+        .await?;
+
+    if result.rows_affected() > 0 {
+        tracing::info!("Pruned {} stale devices", result.rows_affected());
+    }
+
+    Ok(())
+}
+
+/// Background task: permanently deletes peers that have been in the trash
+/// longer than the configured retention.
+pub async fn run_trash_purge(state: AppState) {
+    if state.config.trash_retention_days == 0 {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = purge_trash(&state).await {
+            tracing::error!("Failed to purge expired trash: {}", e);
+        }
+    }
+}
+
+async fn purge_trash(state: &AppState) -> Result<(), sqlx::Error> {
+    let mut tx = state.db.begin().await?;
+
+    let expired: Vec<i64> =
+        sqlx::query_scalar("SELECT id FROM peers WHERE deleted_at < datetime('now', ?)")
+            .bind(format!("-{} days", state.config.trash_retention_days))
+            .fetch_all(&mut *tx)
+            .await?;
+    let purged = purge_peers(&mut tx, &expired).await?;
+
+    tx.commit().await?;
+
+    if purged > 0 {
+        tracing::info!("Purged {} peers from the trash", purged);
+    }
+
+    Ok(())
+}
@@ -4,9 +4,12 @@ mod auth;
 mod config;
 mod db;
 mod error;
+mod jobs;
 mod models;
 mod routes;
 mod state;
+#[cfg(test)]
+mod test_support;
 
 use axum::Router;
 use std::net::SocketAddr;
@@ -15,6 +18,7 @@ use tower_http::trace::TraceLayer;
 use tracing_subscriber::EnvFilter;
 
 use crate::auth::password::hash_password;
+use crate::auth::peer_password::PeerPasswordKeys;
 use crate::config::Config;
 use crate::state::AppState;
 
@@ -60,16 +64,24 @@ async fn main() {
         );
     }
 
+    let peer_keys = PeerPasswordKeys::new(&config.peer_password_keys)
+        .expect("Invalid peer_password_keys");
+
     let state = AppState {
         db: pool,
         config: config.clone(),
+        peer_keys,
     };
 
+    tokio::spawn(jobs::run_device_monitor(state.clone()));
+    tokio::spawn(jobs::run_trash_purge(state.clone()));
+
     // CORS layer — permissive for development, restrict in production
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+        .expose_headers([axum::http::header::ETAG]);
 
     let app = Router::new()
         .merge(routes::api_router())
@@ -1,11 +1,19 @@
 use serde::{Deserialize, Serialize};
 
+use crate::models::custom_field::CustomFieldPayload;
+use crate::models::peer::PeerPayload;
+use crate::models::tag::TagPayload;
+
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct AddressBook {
     pub guid: String,
     pub name: String,
     pub owner_id: i64,
     pub is_personal: bool,
+    pub revision: i64,
+    pub modified_at: i64,
+    /// Peer limit for this book; `None` falls back to `max_peers_per_ab`.
+    pub max_peers: Option<i64>,
     pub created_at: String,
 }
 
@@ -22,6 +30,8 @@ pub struct AbProfile {
     pub owner: String,
     pub rule: i32,
     pub note: String,
+    /// Current change revision, see GET /api/ab/changes/{guid}.
+    pub revision: i64,
 }
 
 /// Response for GET /api/ab/shared/profiles.
@@ -31,6 +41,25 @@ pub struct AbSharedProfilesResponse {
     pub total: i64,
 }
 
+/// Query for GET /api/ab/changes/{guid}.
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    #[serde(default)]
+    pub since: i64,
+}
+
+/// Response for GET /api/ab/changes/{guid}: everything that changed after `since`.
+#[derive(Debug, Serialize)]
+pub struct AbChangesResponse {
+    pub revision: i64,
+    /// Set when `since` is ahead of the book, so the client must drop its copy.
+    pub reset: bool,
+    pub peers: Vec<PeerPayload>,
+    pub tags: Vec<TagPayload>,
+    pub deleted_peers: Vec<String>,
+    pub deleted_tags: Vec<String>,
+}
+
 /// Legacy address book format (GET /api/ab).
 /// The `data` field is a JSON *string* containing serialized peers/tags.
 #[derive(Debug, Serialize)]
@@ -61,6 +90,19 @@ pub struct LegacyPeer {
     pub alias: String,
     #[serde(default)]
     pub tags: Vec<String>,
+    /// Live device state, only sent when requested with `with_device`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub online: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_online: Option<String>,
+}
+
+/// Query for GET /api/ab.
+#[derive(Debug, Deserialize)]
+pub struct LegacyAbQuery {
+    /// Merge live data from `devices` into the returned peers.
+    #[serde(default)]
+    pub with_device: bool,
 }
 
 /// Legacy POST /api/ab request body.
@@ -68,3 +110,81 @@ pub struct LegacyPeer {
 pub struct LegacyAbUpdateRequest {
     pub data: String,
 }
+
+/// Query for GET /api/ab/settings.
+#[derive(Debug, Deserialize)]
+pub struct AbSettingsQuery {
+    /// Report the limit of this book instead of the global one.
+    #[serde(default)]
+    pub ab: String,
+}
+
+/// Body for PUT /api/ab/settings/{guid}.
+#[derive(Debug, Deserialize)]
+pub struct UpdateAbSettingsRequest {
+    /// Peer limit for the book (0 = unlimited); `null` reverts to the global limit.
+    pub max_peer_one_ab: Option<i64>,
+}
+
+/// Query for GET /api/ab/export/{guid}.
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// `json` (default) or `csv`.
+    #[serde(default)]
+    pub format: String,
+}
+
+/// A book as exported by GET /api/ab/export/{guid}?format=json.
+/// Saved peer passwords are not exported.
+#[derive(Debug, Serialize)]
+pub struct AbExport {
+    pub name: String,
+    pub tags: Vec<ExportedTag>,
+    pub fields: Vec<CustomFieldPayload>,
+    pub peers: Vec<PeerPayload>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportedTag {
+    pub name: String,
+    pub color: i64,
+}
+
+/// Query for POST /api/ab/import/{guid}.
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    /// `json` (default) or `csv`.
+    #[serde(default)]
+    pub format: String,
+    /// `merge` (default) adds and updates peers; `replace` also removes the
+    /// peers and tags that are not in the file.
+    #[serde(default)]
+    pub mode: String,
+    /// Report what the import would do without applying it.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// A problem with an imported file. `row` counts peers from 1 (CSV data rows,
+/// not counting the header); it is left out for problems with the whole file.
+#[derive(Debug, Serialize)]
+pub struct ImportError {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub row: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub error: String,
+}
+
+/// Outcome of an import. Nothing is applied when there are errors or on a dry run.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub applied: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub deleted: usize,
+    pub tags_created: usize,
+    pub tags_deleted: usize,
+    pub errors: Vec<ImportError>,
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+/// A typed peer field defined for one address book.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CustomField {
+    pub id: i64,
+    pub ab_guid: String,
+    pub name: String,
+    /// `string`, `number`, `enum` or `date` (`YYYY-MM-DD`).
+    pub field_type: String,
+    /// JSON array of the allowed values of an `enum` field.
+    pub options: String,
+    pub created_at: String,
+}
+
+impl CustomField {
+    pub fn option_list(&self) -> Vec<String> {
+        serde_json::from_str(&self.options).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CustomFieldPayload {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+impl From<&CustomField> for CustomFieldPayload {
+    fn from(field: &CustomField) -> Self {
+        CustomFieldPayload {
+            name: field.name.clone(),
+            field_type: field.field_type.clone(),
+            options: field.option_list(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCustomFieldRequest {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: String,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+/// Only the options of an `enum` field can be changed.
+#[derive(Debug, Deserialize)]
+pub struct UpdateCustomFieldRequest {
+    pub options: Vec<String>,
+}
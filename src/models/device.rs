@@ -1,4 +1,44 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+/// Database row for a device reporting through heartbeat/sysinfo.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Device {
+    pub id: i64,
+    pub rustdesk_id: String,
+    pub uuid: String,
+    pub hostname: String,
+    pub platform: String,
+    pub os: String,
+    pub cpu: String,
+    pub memory: String,
+    pub version: String,
+    pub user_id: Option<i64>,
+    pub device_group_id: Option<i64>,
+    /// Enrollment state: `approved`, `pending` or `rejected`.
+    pub status: String,
+    pub online: bool,
+    pub last_online: String,
+    pub created_at: String,
+}
+
+/// Device list item, joined with the owning user and device group names.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct DeviceListItem {
+    pub id: i64,
+    pub rustdesk_id: String,
+    pub hostname: String,
+    pub platform: String,
+    pub os: String,
+    pub version: String,
+    pub user_id: Option<i64>,
+    pub username: Option<String>,
+    pub device_group_id: Option<i64>,
+    pub device_group_name: Option<String>,
+    pub status: String,
+    pub online: bool,
+    pub last_online: String,
+    pub created_at: String,
+}
 
 #[derive(Debug, Deserialize)]
 pub struct HeartbeatRequest {
@@ -10,6 +50,9 @@ pub struct HeartbeatRequest {
     pub modified_at: i64,
     #[serde(default)]
     pub ver: i64,
+    /// IDs of the connections currently open on the device.
+    #[serde(default)]
+    pub conns: Vec<i32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,6 +75,54 @@ pub struct SysinfoRequest {
     pub version: String,
 }
 
+/// One recorded change of a device's reported system info.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct DeviceSysinfoHistory {
+    pub id: i64,
+    pub device_id: i64,
+    pub hostname: String,
+    pub os: String,
+    pub cpu: String,
+    pub memory: String,
+    pub version: String,
+    pub recorded_at: String,
+}
+
+/// Inventory report row: number of devices sharing a value (OS, version).
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct InventoryCount {
+    pub value: String,
+    pub count: i64,
+}
+
+/// A server command queued for a device and delivered in the heartbeat response.
+/// `command` is one of `sysinfo`, `disconnect` or `strategy`; `status` moves
+/// from `pending` to `delivered` to `acked`.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct DeviceCommand {
+    pub id: i64,
+    pub device_id: i64,
+    pub command: String,
+    pub payload: String,
+    pub status: String,
+    pub created_by: Option<i64>,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+    pub acked_at: Option<String>,
+}
+
+/// Admin API: queue a command for a device.
+#[derive(Debug, Deserialize)]
+pub struct EnqueueCommandRequest {
+    pub command: String,
+    /// Connection IDs to close (`disconnect`).
+    #[serde(default)]
+    pub conns: Vec<i32>,
+    /// Client config options to apply (`strategy`).
+    #[serde(default)]
+    pub options: std::collections::HashMap<String, String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AuditRequest {
     #[serde(default)]
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DeviceGroup {
+    pub id: i64,
+    pub name: String,
+    pub note: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceGroupListItem {
+    pub id: i64,
+    pub name: String,
+    pub note: String,
+    /// User groups whose members can see the devices in this group.
+    pub user_groups: Vec<i64>,
+    pub device_count: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDeviceGroupRequest {
+    pub name: String,
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub user_groups: Vec<i64>,
+}
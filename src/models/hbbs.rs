@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+/// Row of the `peer` table in hbbs's `db_v2.sqlite3`.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct HbbsPeer {
+    pub id: String,
+    pub uuid: Vec<u8>,
+    pub created_at: Option<String>,
+    /// JSON kept by hbbs, holding the IP the ID last registered from.
+    pub info: Option<String>,
+}
+
+impl HbbsPeer {
+    pub fn ip(&self) -> String {
+        self.info
+            .as_deref()
+            .and_then(|info| serde_json::from_str::<serde_json::Value>(info).ok())
+            .and_then(|info| info.get("ip").and_then(|ip| ip.as_str()).map(String::from))
+            .unwrap_or_default()
+    }
+}
+
+/// An ID registered with hbbs, as listed by GET /api/ab/hbbs/peers.
+#[derive(Debug, Serialize)]
+pub struct HbbsPeerPayload {
+    pub id: String,
+    pub created_at: String,
+    pub ip: String,
+}
+
+impl From<&HbbsPeer> for HbbsPeerPayload {
+    fn from(peer: &HbbsPeer) -> Self {
+        HbbsPeerPayload {
+            id: peer.id.clone(),
+            created_at: peer.created_at.clone().unwrap_or_default(),
+            ip: peer.ip(),
+        }
+    }
+}
+
+/// Request to add hbbs IDs to an address book.
+#[derive(Debug, Deserialize)]
+pub struct HbbsImportRequest {
+    pub ab: String,
+    /// IDs to add; empty adds every ID not yet in an address book.
+    #[serde(default)]
+    pub ids: Vec<String>,
+    /// Also create `devices` entries for IDs that have none.
+    #[serde(default)]
+    pub devices: bool,
+}
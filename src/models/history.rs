@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+
+/// A row of `ab_history`, joined with the acting user's name.
+#[derive(Debug, Clone, FromRow)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub ab_guid: String,
+    pub kind: String,
+    pub row_id: i64,
+    pub name: String,
+    pub action: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub user_id: Option<i64>,
+    pub username: Option<String>,
+    pub source: String,
+    pub revision: i64,
+    pub created_at: String,
+}
+
+/// History entry as returned by the API.
+#[derive(Debug, Serialize)]
+pub struct HistoryPayload {
+    pub id: i64,
+    pub kind: String,
+    pub name: String,
+    /// `create`, `update`, `delete` or `restore`.
+    pub action: String,
+    pub old_value: Option<Value>,
+    pub new_value: Option<Value>,
+    /// Fields whose value differs between `old_value` and `new_value`.
+    pub changed: Vec<String>,
+    pub user_id: Option<i64>,
+    pub username: Option<String>,
+    /// Endpoint that made the change, e.g. `peer_update` or `legacy`.
+    pub source: String,
+    pub revision: i64,
+    pub created_at: String,
+}
+
+impl From<HistoryEntry> for HistoryPayload {
+    fn from(entry: HistoryEntry) -> Self {
+        let parse = |v: Option<String>| v.and_then(|v| serde_json::from_str::<Value>(&v).ok());
+        let old_value = parse(entry.old_value);
+        let new_value = parse(entry.new_value);
+
+        let empty = serde_json::Map::new();
+        let old_fields = old_value.as_ref().and_then(Value::as_object).unwrap_or(&empty);
+        let new_fields = new_value.as_ref().and_then(Value::as_object).unwrap_or(&empty);
+        let mut changed: Vec<String> = old_fields
+            .keys()
+            .chain(new_fields.keys())
+            .filter(|key| old_fields.get(*key) != new_fields.get(*key))
+            .cloned()
+            .collect();
+        changed.sort();
+        changed.dedup();
+
+        HistoryPayload {
+            id: entry.id,
+            kind: entry.kind,
+            name: entry.name,
+            action: entry.action,
+            old_value,
+            new_value,
+            changed,
+            user_id: entry.user_id,
+            username: entry.username,
+            source: entry.source,
+            revision: entry.revision,
+            created_at: entry.created_at,
+        }
+    }
+}
+
+/// Query for the history listings.
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+pub struct HistoryQuery {
+    #[serde(default)]
+    pub current: i64,
+    #[serde(default = "default_page_size")]
+    pub pageSize: i64,
+    /// Restrict to `peer` or `tag` entries.
+    #[serde(default)]
+    pub kind: String,
+}
+
+fn default_page_size() -> i64 {
+    100
+}
@@ -1,6 +1,11 @@
 pub mod address_book;
+pub mod custom_field;
 pub mod device;
+pub mod device_group;
 pub mod group;
+pub mod hbbs;
+pub mod history;
 pub mod peer;
+pub mod strategy;
 pub mod tag;
 pub mod user;
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct Peer {
@@ -11,8 +13,55 @@ pub struct Peer {
     pub platform: String,
     pub alias: String,
     pub note: String,
+    pub revision: i64,
+    pub version: i64,
     pub created_at: String,
     pub updated_at: String,
+    /// Set while the peer is in the trash.
+    pub deleted_at: Option<String>,
+    /// Saved connection password, encrypted; see `PeerPasswordKeys`.
+    pub password: String,
+    pub force_always_relay: bool,
+    pub rdp_port: String,
+    pub rdp_username: String,
+    pub login_name: String,
+    pub device_group_name: String,
+    pub same_server: Option<bool>,
+    /// JSON object of fields sent by the client that the server doesn't know.
+    pub extra: String,
+    /// JSON object of values for the book's custom fields, see `ab_custom_fields`.
+    pub custom_fields: String,
+}
+
+impl Peer {
+    pub fn extra_fields(&self) -> Map<String, Value> {
+        serde_json::from_str(&self.extra).unwrap_or_default()
+    }
+
+    pub fn custom_field_values(&self) -> Map<String, Value> {
+        serde_json::from_str(&self.custom_fields).unwrap_or_default()
+    }
+}
+
+/// Keep the unknown fields of a client payload worth storing: keys the server
+/// computes itself are dropped.
+pub fn storable_extra(mut extra: Map<String, Value>) -> Map<String, Value> {
+    for key in ["online", "last_online", "version"] {
+        extra.remove(key);
+    }
+    extra
+}
+
+/// A peer's connection password in plain text. Its `Debug` output is redacted
+/// so it never ends up in logs.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PeerPassword(pub String);
+
+impl std::fmt::Debug for PeerPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PeerPassword(***)")
+    }
 }
 
 /// Peer as returned to the RustDesk client.
@@ -26,6 +75,60 @@ pub struct PeerPayload {
     pub alias: String,
     pub tags: Vec<String>,
     pub note: String,
+    /// Row version, bumped on every change; send it back to detect concurrent edits.
+    pub version: i64,
+    /// Live device state, only present when requested with `with_device`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub online: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_online: Option<String>,
+    /// Saved password, only returned to users with full control of the book.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<PeerPassword>,
+    #[serde(rename = "forceAlwaysRelay")]
+    pub force_always_relay: bool,
+    #[serde(rename = "rdpPort")]
+    pub rdp_port: String,
+    #[serde(rename = "rdpUsername")]
+    pub rdp_username: String,
+    #[serde(rename = "loginName")]
+    pub login_name: String,
+    pub device_group_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub same_server: Option<bool>,
+    /// Values of the address book's custom fields.
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub custom_fields: Map<String, Value>,
+    /// Fields the client sent that the server doesn't know, returned as they came.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl PeerPayload {
+    pub fn new(peer: &Peer, tags: Vec<String>) -> Self {
+        PeerPayload {
+            id: peer.rustdesk_id.clone(),
+            hash: peer.hash.clone(),
+            username: peer.username.clone(),
+            hostname: peer.hostname.clone(),
+            platform: peer.platform.clone(),
+            alias: peer.alias.clone(),
+            tags,
+            note: peer.note.clone(),
+            version: peer.version,
+            online: None,
+            last_online: None,
+            password: None,
+            force_always_relay: peer.force_always_relay,
+            rdp_port: peer.rdp_port.clone(),
+            rdp_username: peer.rdp_username.clone(),
+            login_name: peer.login_name.clone(),
+            device_group_name: peer.device_group_name.clone(),
+            same_server: peer.same_server,
+            custom_fields: peer.custom_field_values(),
+            extra: peer.extra_fields(),
+        }
+    }
 }
 
 /// Request to add a peer.
@@ -46,11 +149,36 @@ pub struct AddPeerRequest {
     pub tags: Vec<String>,
     #[serde(default)]
     pub note: String,
+    /// Left out keeps the saved password of an existing peer.
+    #[serde(default)]
+    pub password: Option<PeerPassword>,
+    #[serde(default, rename = "forceAlwaysRelay")]
+    pub force_always_relay: bool,
+    #[serde(default, rename = "rdpPort")]
+    pub rdp_port: String,
+    #[serde(default, rename = "rdpUsername")]
+    pub rdp_username: String,
+    #[serde(default, rename = "loginName")]
+    pub login_name: String,
+    #[serde(default)]
+    pub device_group_name: String,
+    #[serde(default)]
+    pub same_server: Option<bool>,
+    /// Values for the book's custom fields; left out keeps those of an existing peer.
+    #[serde(default)]
+    pub custom_fields: Option<Map<String, Value>>,
+    /// Expected current version; 0 means the peer must not exist yet.
+    #[serde(default)]
+    pub version: Option<i64>,
+    /// Any other fields, stored as they are.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-/// Request to update a peer.
+/// Request to update a peer. Fields left out keep their current value.
 #[derive(Debug, Deserialize)]
 pub struct UpdatePeerRequest {
+    pub id: String,
     #[serde(default)]
     pub hash: Option<String>,
     #[serde(default)]
@@ -65,6 +193,31 @@ pub struct UpdatePeerRequest {
     pub tags: Option<Vec<String>>,
     #[serde(default)]
     pub note: Option<String>,
+    /// An empty password clears the saved one.
+    #[serde(default)]
+    pub password: Option<PeerPassword>,
+    #[serde(default, rename = "forceAlwaysRelay")]
+    pub force_always_relay: Option<bool>,
+    #[serde(default, rename = "rdpPort")]
+    pub rdp_port: Option<String>,
+    #[serde(default, rename = "rdpUsername")]
+    pub rdp_username: Option<String>,
+    #[serde(default, rename = "loginName")]
+    pub login_name: Option<String>,
+    #[serde(default)]
+    pub device_group_name: Option<String>,
+    #[serde(default)]
+    pub same_server: Option<bool>,
+    /// Custom field values to change; `null` clears a field.
+    #[serde(default)]
+    pub custom_fields: Option<Map<String, Value>>,
+    /// Expected current version.
+    #[serde(default)]
+    pub version: Option<i64>,
+    /// Any other fields; each replaces the stored value of the same key and
+    /// `null` removes it.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// Response for GET /api/ab/peers.
@@ -82,4 +235,139 @@ pub struct DeletePeersRequest {
     /// Single ID variant (some client versions send this).
     #[serde(default)]
     pub id: Option<String>,
+    /// Expected current version per RustDesk ID.
+    #[serde(default)]
+    pub versions: HashMap<String, i64>,
+}
+
+/// Query for GET /api/ab/search.
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    pub q: String,
+    #[serde(default = "default_search_limit")]
+    pub limit: i64,
+}
+
+fn default_search_limit() -> i64 {
+    50
+}
+
+/// A peer matching a global search, with the address book containing it.
+#[derive(Debug, Serialize)]
+pub struct PeerSearchHit {
+    pub ab_guid: String,
+    pub ab_name: String,
+    pub peer: PeerPayload,
+    /// FTS5 bm25 score; lower is a better match.
+    pub rank: f64,
+}
+
+/// Peer in an address book's trash.
+#[derive(Debug, Serialize)]
+pub struct TrashedPeerPayload {
+    #[serde(flatten)]
+    pub peer: PeerPayload,
+    pub deleted_at: String,
+}
+
+/// Query for GET /api/ab/duplicates.
+#[derive(Debug, Deserialize)]
+pub struct DuplicatesQuery {
+    /// `id` (default) groups by RustDesk ID, `hostname` by hostname.
+    #[serde(default)]
+    pub by: String,
+}
+
+/// One peer of a duplicate group, with the address book containing it.
+#[derive(Debug, Serialize)]
+pub struct DuplicatePeer {
+    pub ab_guid: String,
+    pub ab_name: String,
+    pub peer: PeerPayload,
+}
+
+/// Peers sharing the same RustDesk ID or hostname.
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroup {
+    pub key: String,
+    pub peers: Vec<DuplicatePeer>,
+}
+
+/// A peer addressed by address book and RustDesk ID.
+#[derive(Debug, Deserialize)]
+pub struct PeerRef {
+    pub ab_guid: String,
+    pub id: String,
+}
+
+/// Request to merge duplicate peers into a canonical entry.
+#[derive(Debug, Deserialize)]
+pub struct MergePeersRequest {
+    pub canonical: PeerRef,
+    pub others: Vec<PeerRef>,
+}
+
+/// Request to move or copy peers from one address book to another.
+#[derive(Debug, Deserialize)]
+pub struct TransferPeersRequest {
+    pub from: String,
+    pub to: String,
+    pub ids: Vec<String>,
+}
+
+/// Request to restore or purge trashed peers.
+#[derive(Debug, Deserialize)]
+pub struct TrashRequest {
+    #[serde(default)]
+    pub ids: Vec<String>,
+    /// Apply to everything in the trash instead of `ids`.
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// One operation of a batch request, selected by its `op` key.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    /// Add or replace a peer; takes the fields of `AddPeerRequest`.
+    Upsert(Box<AddPeerRequest>),
+    Delete {
+        id: String,
+        #[serde(default)]
+        version: Option<i64>,
+    },
+    AddTags {
+        id: String,
+        tags: Vec<String>,
+        #[serde(default)]
+        version: Option<i64>,
+    },
+    RemoveTags {
+        id: String,
+        tags: Vec<String>,
+        #[serde(default)]
+        version: Option<i64>,
+    },
+    SetNote {
+        id: String,
+        note: String,
+        #[serde(default)]
+        version: Option<i64>,
+    },
+}
+
+/// Request for POST /api/ab/peers/batch/{guid}.
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+    pub ops: Vec<BatchOperation>,
+}
+
+/// Outcome of one batch operation.
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    pub op: &'static str,
+    pub id: String,
+    /// `created`, `updated`, `deleted`, `unchanged` or `not_found`.
+    pub result: &'static str,
 }
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// A named set of RustDesk client config options pushed to devices.
+/// `modified_at` is a unix timestamp bumped on every change; the client echoes
+/// it back in its heartbeat.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Strategy {
+    pub id: i64,
+    pub name: String,
+    pub note: String,
+    pub options: String,
+    pub enabled: bool,
+    pub modified_at: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StrategyListItem {
+    pub id: i64,
+    pub name: String,
+    pub note: String,
+    pub options: HashMap<String, String>,
+    pub enabled: bool,
+    pub modified_at: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateStrategyRequest {
+    pub name: String,
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub options: HashMap<String, String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Assignment of a strategy to exactly one device, user or device group.
+/// When several apply to a device, device beats user beats device group.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct StrategyAssignment {
+    pub id: i64,
+    pub strategy_id: i64,
+    pub device_id: Option<i64>,
+    pub user_id: Option<i64>,
+    pub device_group_id: Option<i64>,
+    pub modified_at: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignStrategyRequest {
+    #[serde(default)]
+    pub device_id: Option<i64>,
+    #[serde(default)]
+    pub user_id: Option<i64>,
+    #[serde(default)]
+    pub device_group_id: Option<i64>,
+}
+
+/// The config a device should currently run with, see `effective_strategy`.
+#[derive(Debug, Clone)]
+pub struct EffectiveStrategy {
+    pub version: i64,
+    /// The assigned options, with a push the device hasn't acknowledged yet on top.
+    pub options: Map<String, Value>,
+    /// The assigned options alone; only these are cleared once nothing applies.
+    pub assigned: Map<String, Value>,
+}
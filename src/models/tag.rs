@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct Tag {
@@ -6,6 +7,8 @@ pub struct Tag {
     pub ab_guid: String,
     pub name: String,
     pub color: i64,
+    pub revision: i64,
+    pub version: i64,
 }
 
 /// Tag as returned to the RustDesk client.
@@ -13,6 +16,7 @@ pub struct Tag {
 pub struct TagPayload {
     pub name: String,
     pub color: i64,
+    pub version: i64,
 }
 
 /// Response for GET /api/ab/tags/{guid}.
@@ -37,12 +41,18 @@ fn default_color() -> i64 {
 pub struct RenameTagRequest {
     pub old: String,
     pub new: String,
+    /// Expected current version.
+    #[serde(default)]
+    pub version: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateTagColorRequest {
     pub name: String,
     pub color: i64,
+    /// Expected current version.
+    #[serde(default)]
+    pub version: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -51,4 +61,7 @@ pub struct DeleteTagRequest {
     pub names: Vec<String>,
     #[serde(default)]
     pub name: Option<String>,
+    /// Expected current version per tag name.
+    #[serde(default)]
+    pub versions: HashMap<String, i64>,
 }
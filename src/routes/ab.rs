@@ -1,10 +1,25 @@
-use axum::{extract::State, routing::get, Json, Router};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Json, Router,
+};
 use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
 use crate::auth::middleware::AuthUser;
 use crate::error::ApiError;
 use crate::models::address_book::*;
+use crate::models::peer::Peer;
+use crate::models::tag::Tag;
+use crate::routes::changes::{
+    book_etag, book_revision, check_if_match, content_etag, not_modified, BookChanges,
+};
+use crate::routes::peers::{
+    devices_for_book, peer_limit, resolve_ab_guid, tags_for_peers, PeerQuota,
+};
 use crate::state::AppState;
 
 pub fn routes() -> Router<AppState> {
@@ -13,6 +28,7 @@ pub fn routes() -> Router<AppState> {
         .route("/api/ab/personal", get(get_personal))
         .route("/api/ab/shared/profiles", get(get_shared_profiles))
         .route("/api/ab/settings", get(get_ab_settings))
+        .route("/api/ab/settings/{guid}", put(update_ab_settings))
 }
 
 /// Ensure the user has a personal address book, creating one if needed.
@@ -51,6 +67,11 @@ async fn get_personal(
 ) -> Result<Json<AbPersonalResponse>, ApiError> {
     let guid = ensure_personal_ab(&state.db, claims.user_id, &claims.sub).await?;
 
+    let revision: i64 = sqlx::query_scalar("SELECT revision FROM address_books WHERE guid = ?")
+        .bind(&guid)
+        .fetch_one(&state.db)
+        .await?;
+
     Ok(Json(AbPersonalResponse {
         data: AbProfile {
             guid,
@@ -58,33 +79,62 @@ async fn get_personal(
             owner: claims.sub,
             rule: 3, // full control
             note: String::new(),
+            revision,
         },
     }))
 }
 
 /// GET /api/ab — legacy endpoint, returns the entire address book as a JSON string.
+///
+/// Tagged with the book's ETag unless live device data is merged in, since
+/// that changes without the book's revision moving.
 async fn get_ab_legacy(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
-) -> Result<Json<LegacyAbResponse>, ApiError> {
+    headers: HeaderMap,
+    Query(query): Query<LegacyAbQuery>,
+) -> Result<Response, ApiError> {
     let guid = ensure_personal_ab(&state.db, claims.user_id, &claims.sub).await?;
 
+    // Read peers, tags and their associations in one transaction
+    let mut tx = state.db.begin().await?;
+
+    let etag = if query.with_device {
+        None
+    } else {
+        Some(book_etag(&guid, book_revision(&mut tx, &guid).await?))
+    };
+    if let Some(response) = etag.as_deref().and_then(|etag| not_modified(&headers, etag)) {
+        return Ok(response);
+    }
+
     // Fetch all peers
-    let peers = sqlx::query_as::<_, crate::models::peer::Peer>(
-        "SELECT * FROM peers WHERE ab_guid = ? ORDER BY rustdesk_id",
+    let peers = sqlx::query_as::<_, Peer>(
+        "SELECT * FROM peers WHERE ab_guid = ? AND deleted_at IS NULL ORDER BY rustdesk_id",
     )
     .bind(&guid)
-    .fetch_all(&state.db)
+    .fetch_all(&mut *tx)
     .await?;
 
     // Fetch all tags
-    let tags = sqlx::query_as::<_, crate::models::tag::Tag>(
+    let tags = sqlx::query_as::<_, Tag>(
         "SELECT * FROM tags WHERE ab_guid = ? ORDER BY name",
     )
     .bind(&guid)
-    .fetch_all(&state.db)
+    .fetch_all(&mut *tx)
     .await?;
 
+    let peer_ids: Vec<i64> = peers.iter().map(|p| p.id).collect();
+    let mut peer_tags = tags_for_peers(&mut tx, &peer_ids).await?;
+
+    let devices = if query.with_device {
+        devices_for_book(&mut tx, &guid).await?
+    } else {
+        HashMap::new()
+    };
+
+    tx.commit().await?;
+
     // Build tag names list
     let tag_names: Vec<String> = tags.iter().map(|t| t.name.clone()).collect();
 
@@ -98,23 +148,31 @@ async fn get_ab_legacy(
     // Build legacy peers with their tags
     let mut legacy_peers = Vec::new();
     for peer in &peers {
-        let peer_tags: Vec<String> = sqlx::query_scalar(
-            "SELECT t.name FROM tags t JOIN peer_tags pt ON t.id = pt.tag_id WHERE pt.peer_id = ?",
-        )
-        .bind(peer.id)
-        .fetch_all(&state.db)
-        .await
-        .unwrap_or_default();
-
-        legacy_peers.push(LegacyPeer {
+        let mut legacy_peer = LegacyPeer {
             id: peer.rustdesk_id.clone(),
             hash: peer.hash.clone(),
             username: peer.username.clone(),
             hostname: peer.hostname.clone(),
             platform: peer.platform.clone(),
             alias: peer.alias.clone(),
-            tags: peer_tags,
-        });
+            tags: peer_tags.remove(&peer.id).unwrap_or_default(),
+            online: None,
+            last_online: None,
+        };
+        if query.with_device {
+            let device = devices.get(&peer.rustdesk_id);
+            if let Some(device) = device {
+                if !device.hostname.is_empty() {
+                    legacy_peer.hostname = device.hostname.clone();
+                }
+                if !device.platform.is_empty() {
+                    legacy_peer.platform = device.platform.clone();
+                }
+                legacy_peer.last_online = Some(device.last_online.clone());
+            }
+            legacy_peer.online = Some(device.map(|d| d.online).unwrap_or(false));
+        }
+        legacy_peers.push(legacy_peer);
     }
 
     let ab_data = LegacyAbData {
@@ -126,13 +184,23 @@ async fn get_ab_legacy(
     let data_str = serde_json::to_string(&ab_data)
         .map_err(|e| ApiError::Internal(e.to_string()))?;
 
-    Ok(Json(LegacyAbResponse { data: data_str }))
+    let body = Json(LegacyAbResponse { data: data_str });
+    Ok(match etag {
+        Some(etag) => ([(header::ETAG, etag)], body).into_response(),
+        None => body.into_response(),
+    })
 }
 
 /// POST /api/ab — legacy endpoint, replaces the entire address book from a JSON string.
+///
+/// The replace is applied as a diff inside one transaction: peers and tags that
+/// are still present keep their row (and with it `note`, `created_at` and tag
+/// associations); only what changed is inserted, updated or deleted. Peers
+/// missing from the new data are moved to the trash.
 async fn update_ab_legacy(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
+    headers: HeaderMap,
     Json(req): Json<LegacyAbUpdateRequest>,
 ) -> Result<Json<Value>, ApiError> {
     let guid = ensure_personal_ab(&state.db, claims.user_id, &claims.sub).await?;
@@ -141,82 +209,196 @@ async fn update_ab_legacy(
         .map_err(|e| ApiError::BadRequest(format!("Invalid address book data: {}", e)))?;
 
     // Parse tag_colors
-    let tag_colors: std::collections::HashMap<String, i64> =
+    let tag_colors: HashMap<String, i64> =
         serde_json::from_str(&ab_data.tag_colors).unwrap_or_default();
 
-    // Delete existing data and replace atomically
-    // Delete peer_tags first (foreign key), then peers, then tags
-    sqlx::query("DELETE FROM peer_tags WHERE peer_id IN (SELECT id FROM peers WHERE ab_guid = ?)")
-        .bind(&guid)
-        .execute(&state.db)
-        .await?;
-    sqlx::query("DELETE FROM peers WHERE ab_guid = ?")
-        .bind(&guid)
-        .execute(&state.db)
-        .await?;
-    sqlx::query("DELETE FROM tags WHERE ab_guid = ?")
-        .bind(&guid)
-        .execute(&state.db)
-        .await?;
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+    let quota = PeerQuota::load(&mut tx, &state.config, &guid).await?;
+    let mut changes = BookChanges::new(&guid, claims.user_id, "legacy");
 
-    // Insert tags
-    for tag_name in &ab_data.tags {
-        let color = tag_colors.get(tag_name).copied().unwrap_or(4278190080);
-        sqlx::query("INSERT INTO tags (ab_guid, name, color) VALUES (?, ?, ?)")
+    // Tags: drop the ones no longer listed, add new ones, update changed colours
+    let existing_tags: HashMap<String, Tag> =
+        sqlx::query_as::<_, Tag>("SELECT * FROM tags WHERE ab_guid = ?")
             .bind(&guid)
-            .bind(tag_name)
-            .bind(color)
-            .execute(&state.db)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|t| (t.name.clone(), t))
+            .collect();
+    let wanted_tags: HashSet<&String> = ab_data.tags.iter().collect();
+
+    for (name, tag) in &existing_tags {
+        if wanted_tags.contains(name) {
+            continue;
+        }
+        changes.untag_peers(&mut tx, tag.id).await?;
+        sqlx::query("DELETE FROM tags WHERE id = ?")
+            .bind(tag.id)
+            .execute(&mut *tx)
             .await?;
+        changes.delete_tag(&mut tx, tag).await?;
     }
 
-    // Insert peers and their tag associations
-    for peer in &ab_data.peers {
-        let result = sqlx::query(
-            "INSERT INTO peers (ab_guid, rustdesk_id, hash, username, hostname, platform, alias) VALUES (?, ?, ?, ?, ?, ?, ?)",
-        )
-        .bind(&guid)
-        .bind(&peer.id)
-        .bind(&peer.hash)
-        .bind(&peer.username)
-        .bind(&peer.hostname)
-        .bind(&peer.platform)
-        .bind(&peer.alias)
-        .execute(&state.db)
-        .await?;
-
-        let peer_id = result.last_insert_rowid();
+    let mut tag_ids: HashMap<&String, i64> = HashMap::new();
+    for tag_name in &ab_data.tags {
+        if tag_ids.contains_key(tag_name) {
+            continue;
+        }
+        let tag_id = match existing_tags.get(tag_name) {
+            Some(tag) => {
+                let color = tag_colors.get(tag_name).copied().unwrap_or(tag.color);
+                if color != tag.color {
+                    sqlx::query("UPDATE tags SET color = ? WHERE id = ?")
+                        .bind(color)
+                        .bind(tag.id)
+                        .execute(&mut *tx)
+                        .await?;
+                    changes.touch_tag(&mut tx, tag.id).await?;
+                }
+                tag.id
+            }
+            None => {
+                let color = tag_colors.get(tag_name).copied().unwrap_or(4278190080);
+                let tag_id = sqlx::query("INSERT INTO tags (ab_guid, name, color) VALUES (?, ?, ?)")
+                    .bind(&guid)
+                    .bind(tag_name)
+                    .bind(color)
+                    .execute(&mut *tx)
+                    .await?
+                    .last_insert_rowid();
+                changes.touch_tag(&mut tx, tag_id).await?;
+                tag_id
+            }
+        };
+        tag_ids.insert(tag_name, tag_id);
+    }
 
-        for tag_name in &peer.tags {
-            let tag_id: Option<i64> = sqlx::query_scalar(
-                "SELECT id FROM tags WHERE ab_guid = ? AND name = ?",
-            )
+    // Peers: same diff by RustDesk ID; the last entry wins on duplicates.
+    // Trashed peers are included so that listing one again restores it.
+    let existing_peers: HashMap<String, Peer> =
+        sqlx::query_as::<_, Peer>("SELECT * FROM peers WHERE ab_guid = ?")
             .bind(&guid)
-            .bind(tag_name)
-            .fetch_optional(&state.db)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|p| (p.rustdesk_id.clone(), p))
+            .collect();
+
+    let mut current_peer_tags: HashMap<i64, HashSet<i64>> = HashMap::new();
+    let rows: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT pt.peer_id, pt.tag_id FROM peer_tags pt
+         JOIN peers p ON p.id = pt.peer_id WHERE p.ab_guid = ?",
+    )
+    .bind(&guid)
+    .fetch_all(&mut *tx)
+    .await?;
+    for (peer_id, tag_id) in rows {
+        current_peer_tags.entry(peer_id).or_default().insert(tag_id);
+    }
+
+    let incoming: HashMap<&String, &LegacyPeer> =
+        ab_data.peers.iter().map(|p| (&p.id, p)).collect();
+
+    for (rustdesk_id, peer) in &existing_peers {
+        if incoming.contains_key(rustdesk_id) || peer.deleted_at.is_some() {
+            continue;
+        }
+        sqlx::query("UPDATE peers SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(peer.id)
+            .execute(&mut *tx)
             .await?;
+        changes.delete_peer(&mut tx, peer).await?;
+    }
 
-            if let Some(tag_id) = tag_id {
-                sqlx::query("INSERT OR IGNORE INTO peer_tags (peer_id, tag_id) VALUES (?, ?)")
-                    .bind(peer_id)
-                    .bind(tag_id)
-                    .execute(&state.db)
+    for (rustdesk_id, peer) in &incoming {
+        let (peer_id, changed) = match existing_peers.get(*rustdesk_id) {
+            Some(existing) => {
+                let changed = existing.hash != peer.hash
+                    || existing.username != peer.username
+                    || existing.hostname != peer.hostname
+                    || existing.platform != peer.platform
+                    || existing.alias != peer.alias
+                    || existing.deleted_at.is_some();
+                if changed {
+                    sqlx::query(
+                        "UPDATE peers SET hash = ?, username = ?, hostname = ?, platform = ?, alias = ?,
+                             updated_at = CURRENT_TIMESTAMP, deleted_at = NULL
+                         WHERE id = ?",
+                    )
+                    .bind(&peer.hash)
+                    .bind(&peer.username)
+                    .bind(&peer.hostname)
+                    .bind(&peer.platform)
+                    .bind(&peer.alias)
+                    .bind(existing.id)
+                    .execute(&mut *tx)
                     .await?;
+                }
+                (existing.id, changed)
+            }
+            None => {
+                let peer_id = sqlx::query(
+                    "INSERT INTO peers (ab_guid, rustdesk_id, hash, username, hostname, platform, alias) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(&guid)
+                .bind(&peer.id)
+                .bind(&peer.hash)
+                .bind(&peer.username)
+                .bind(&peer.hostname)
+                .bind(&peer.platform)
+                .bind(&peer.alias)
+                .execute(&mut *tx)
+                .await?
+                .last_insert_rowid();
+                (peer_id, true)
             }
+        };
+
+        // Tags the peer references but the book doesn't list are ignored
+        let wanted: HashSet<i64> = peer
+            .tags
+            .iter()
+            .filter_map(|name| tag_ids.get(name).copied())
+            .collect();
+        let current = current_peer_tags.remove(&peer_id).unwrap_or_default();
+        if wanted == current {
+            if changed {
+                changes.touch_peer(&mut tx, peer_id).await?;
+            }
+            continue;
+        }
+
+        sqlx::query("DELETE FROM peer_tags WHERE peer_id = ?")
+            .bind(peer_id)
+            .execute(&mut *tx)
+            .await?;
+        for tag_id in wanted {
+            sqlx::query("INSERT OR IGNORE INTO peer_tags (peer_id, tag_id) VALUES (?, ?)")
+                .bind(peer_id)
+                .bind(tag_id)
+                .execute(&mut *tx)
+                .await?;
         }
+        changes.touch_peer(&mut tx, peer_id).await?;
     }
 
+    quota.check(&mut tx).await?;
+    tx.commit().await?;
+
     Ok(Json(json!({})))
 }
 
 /// GET /api/ab/shared/profiles — returns shared address books the user has access to.
+/// The list spans several books, so its ETag is a hash of the response body.
 async fn get_shared_profiles(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
-) -> Result<Json<AbSharedProfilesResponse>, ApiError> {
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
     // Find address books shared with this user directly or via groups
-    let shared: Vec<(String, String, i64, i32)> = sqlx::query_as(
-        "SELECT ab.guid, ab.name, ab.owner_id, s.rule
+    let shared: Vec<(String, String, i64, i32, i64)> = sqlx::query_as(
+        "SELECT ab.guid, ab.name, ab.owner_id, s.rule, ab.revision
          FROM address_books ab
          JOIN ab_shares s ON ab.guid = s.ab_guid
          WHERE (s.user_id = ? OR s.group_id IN (SELECT group_id FROM user_groups WHERE user_id = ?))
@@ -228,7 +410,7 @@ async fn get_shared_profiles(
     .await?;
 
     let mut profiles = Vec::new();
-    for (guid, name, owner_id, rule) in shared {
+    for (guid, name, owner_id, rule, revision) in shared {
         let owner_name: String = sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
             .bind(owner_id)
             .fetch_optional(&state.db)
@@ -241,19 +423,154 @@ async fn get_shared_profiles(
             owner: owner_name,
             rule,
             note: String::new(),
+            revision,
         });
     }
 
     let total = profiles.len() as i64;
-    Ok(Json(AbSharedProfilesResponse {
+    let body = serde_json::to_vec(&AbSharedProfilesResponse {
         data: profiles,
         total,
-    }))
+    })
+    .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    let etag = content_etag(&body);
+    if let Some(response) = not_modified(&headers, &etag) {
+        return Ok(response);
+    }
+    Ok((
+        [
+            (header::ETAG, etag),
+            (header::CONTENT_TYPE, "application/json".to_string()),
+        ],
+        body,
+    )
+        .into_response())
 }
 
-/// GET /api/ab/settings — address book configuration.
-async fn get_ab_settings(AuthUser(_claims): AuthUser) -> Json<Value> {
-    Json(json!({
-        "max_peer_one_ab": 0
-    }))
+fn require_admin(claims: &crate::auth::jwt::Claims) -> Result<(), ApiError> {
+    if !claims.is_admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+    Ok(())
+}
+
+/// GET /api/ab/settings — address book configuration. `max_peer_one_ab` is the
+/// global peer limit, or that of the book given as `ab` (0 = unlimited).
+async fn get_ab_settings(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<AbSettingsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let max_peers = if query.ab.is_empty() {
+        state.config.max_peers_per_ab as i64
+    } else {
+        let guid = resolve_ab_guid(&state.db, claims.user_id, &query.ab).await?;
+        let mut conn = state.db.acquire().await?;
+        peer_limit(&mut conn, &state.config, &guid).await?
+    };
+
+    Ok(Json(json!({
+        "max_peer_one_ab": max_peers
+    })))
+}
+
+/// PUT /api/ab/settings/{guid} — set an address book's own peer limit, or
+/// clear it with `null` to fall back to the global limit.
+async fn update_ab_settings(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(guid): Path<String>,
+    Json(req): Json<UpdateAbSettingsRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    if req.max_peer_one_ab.is_some_and(|n| n < 0) {
+        return Err(ApiError::BadRequest("Peer limit cannot be negative".to_string()));
+    }
+
+    let result = sqlx::query("UPDATE address_books SET max_peers = ? WHERE guid = ?")
+        .bind(req.max_peer_one_ab)
+        .bind(&guid)
+        .execute(&state.db)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound("Address book not found".to_string()));
+    }
+
+    Ok(Json(json!({})))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+
+    use crate::test_support::TestApp;
+
+    async fn post_legacy(app: &TestApp, tags: Value, peers: Value) -> StatusCode {
+        let data = json!({ "tags": tags, "peers": peers, "tag_colors": "{}" });
+        let body = json!({ "data": data.to_string() });
+        app.call("POST", "/api/ab", Some(body)).await.0
+    }
+
+    /// The book as the legacy GET returns it.
+    async fn get_legacy(app: &TestApp) -> Value {
+        let (_, body) = app.call("GET", "/api/ab", None).await;
+        serde_json::from_str(body["data"].as_str().unwrap()).unwrap()
+    }
+
+    async fn peer_row(app: &TestApp, rustdesk_id: &str) -> (i64, String) {
+        sqlx::query_as("SELECT id, note FROM peers WHERE rustdesk_id = ?")
+            .bind(rustdesk_id)
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn legacy_saves_apply_as_a_diff() {
+        let app = TestApp::new().await;
+        let peers = json!([
+            { "id": "100", "alias": "a", "tags": ["web", "db"] },
+            { "id": "200" },
+        ]);
+        assert_eq!(post_legacy(&app, json!(["web", "db"]), peers).await, StatusCode::OK);
+        let note = json!({ "id": "100", "note": "kept" });
+        app.call("PUT", "/api/ab/peer/update/personal", Some(note)).await;
+        let (peer_100, _) = peer_row(&app, "100").await;
+        let (peer_200, _) = peer_row(&app, "200").await;
+
+        // A save failing halfway leaves the book as it was
+        sqlx::query(
+            "CREATE TRIGGER fail_insert BEFORE INSERT ON peers WHEN NEW.rustdesk_id = 'boom'
+             BEGIN SELECT RAISE(ABORT, 'boom'); END",
+        )
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+        let status = post_legacy(&app, json!([]), json!([{ "id": "boom" }])).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let book = get_legacy(&app).await;
+        assert_eq!(book["tags"], json!(["db", "web"]));
+        assert_eq!(book["peers"].as_array().unwrap().len(), 2);
+
+        // Leaving out a peer trashes it, dropping a tag untags its peers, and
+        // untouched peers keep their row and note
+        let peers = json!([{ "id": "100", "alias": "a", "tags": ["web", "db"] }]);
+        assert_eq!(post_legacy(&app, json!(["web"]), peers).await, StatusCode::OK);
+        let book = get_legacy(&app).await;
+        assert_eq!(book["tags"], json!(["web"]));
+        assert_eq!(book["peers"].as_array().unwrap().len(), 1);
+        assert_eq!(book["peers"][0]["tags"], json!(["web"]));
+        assert_eq!(peer_row(&app, "100").await, (peer_100, "kept".to_string()));
+        let (_, trash) = app.call("GET", "/api/ab/trash/personal", None).await;
+        assert_eq!(trash["data"][0]["id"], "200");
+
+        // Listing it again brings the same peer back
+        let peers = json!([{ "id": "100", "tags": ["web"] }, { "id": "200" }]);
+        assert_eq!(post_legacy(&app, json!(["web"]), peers).await, StatusCode::OK);
+        assert_eq!(get_legacy(&app).await["peers"].as_array().unwrap().len(), 2);
+        assert_eq!(peer_row(&app, "200").await.0, peer_200);
+    }
 }
@@ -1,4 +1,9 @@
-use axum::{extract::State, routing::{get, post}, Json, Router};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    routing::{get, post},
+    Json, Router,
+};
 use serde_json::{json, Value};
 
 use crate::auth::jwt::create_token;
@@ -6,6 +11,7 @@ use crate::auth::middleware::AuthUser;
 use crate::auth::password::verify_password;
 use crate::error::ApiError;
 use crate::models::user::{LoginRequest, LoginResponse, UserPayload};
+use crate::routes::devices::{admit_device, claim_device, EnrollmentQuery};
 use crate::state::AppState;
 
 pub fn routes() -> Router<AppState> {
@@ -17,6 +23,8 @@ pub fn routes() -> Router<AppState> {
 
 async fn login(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(enrollment): Query<EnrollmentQuery>,
     Json(req): Json<LoginRequest>,
 ) -> Result<Json<LoginResponse>, ApiError> {
     let user = sqlx::query_as::<_, crate::models::user::User>(
@@ -49,6 +57,18 @@ async fn login(
         .await
         .ok();
 
+    // The client logs in from a device: make this user its owner if it has none
+    // yet, as long as the device is admitted and not rejected
+    if !req.id.is_empty() {
+        let admitted =
+            admit_device(&state, &req.id, &req.uuid, &headers, &enrollment.token).await?;
+        if let Some((device_id, status)) = admitted {
+            if status != "rejected" {
+                claim_device(&state.db, device_id, user.id).await?;
+            }
+        }
+    }
+
     Ok(Json(LoginResponse {
         access_token: token,
         token_type: "access_token".to_string(),
@@ -0,0 +1,287 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use sqlx::SqliteConnection;
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::peer::*;
+use crate::routes::changes::{check_if_match, BookChanges};
+use crate::routes::peers::{check_peer_version, resolve_writable_ab, upsert_peer, PeerQuota};
+use crate::state::AppState;
+
+/// Upper bound on the number of operations in one batch.
+const MAX_BATCH_OPS: usize = 1000;
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/api/ab/peers/batch/{guid}", post(batch_peers))
+}
+
+/// Prefix an error with the position of the operation that caused it.
+fn at_operation(index: usize, err: ApiError) -> ApiError {
+    let prefix = |msg: String| format!("Operation {}: {}", index, msg);
+    match err {
+        ApiError::BadRequest(msg) => ApiError::BadRequest(prefix(msg)),
+        ApiError::Forbidden(msg) => ApiError::Forbidden(prefix(msg)),
+        ApiError::NotFound(msg) => ApiError::NotFound(prefix(msg)),
+        ApiError::Conflict(msg, current) => ApiError::Conflict(prefix(msg), current),
+        other => other,
+    }
+}
+
+/// The live peer an operation applies to; missing peers are an error.
+async fn existing_peer(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+    rustdesk_id: &str,
+    version: Option<i64>,
+) -> Result<Peer, ApiError> {
+    check_peer_version(conn, ab_guid, rustdesk_id, version)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Peer '{}' not found", rustdesk_id)))
+}
+
+/// Tag a peer with the named tags of its book. Returns whether any were new.
+async fn add_peer_tags(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+    peer_id: i64,
+    tags: &[String],
+) -> Result<bool, ApiError> {
+    let mut added = false;
+    for tag_name in tags {
+        let tag_id: Option<i64> =
+            sqlx::query_scalar("SELECT id FROM tags WHERE ab_guid = ? AND name = ?")
+                .bind(ab_guid)
+                .bind(tag_name)
+                .fetch_optional(&mut *conn)
+                .await?;
+        let Some(tag_id) = tag_id else {
+            return Err(ApiError::NotFound(format!("Tag '{}' not found", tag_name)));
+        };
+
+        let result = sqlx::query("INSERT OR IGNORE INTO peer_tags (peer_id, tag_id) VALUES (?, ?)")
+            .bind(peer_id)
+            .bind(tag_id)
+            .execute(&mut *conn)
+            .await?;
+        added |= result.rows_affected() > 0;
+    }
+    Ok(added)
+}
+
+/// Untag a peer. Returns whether it had any of the tags.
+async fn remove_peer_tags(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+    peer_id: i64,
+    tags: &[String],
+) -> Result<bool, ApiError> {
+    let names = serde_json::to_string(tags).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let result = sqlx::query(
+        "DELETE FROM peer_tags WHERE peer_id = ? AND tag_id IN (
+             SELECT id FROM tags WHERE ab_guid = ? AND name IN (SELECT value FROM json_each(?))
+         )",
+    )
+    .bind(peer_id)
+    .bind(ab_guid)
+    .bind(names)
+    .execute(conn)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Apply one operation, recording what it changed.
+async fn apply_operation(
+    conn: &mut SqliteConnection,
+    state: &AppState,
+    changes: &mut BookChanges,
+    ab_guid: &str,
+    op: BatchOperation,
+) -> Result<BatchResult, ApiError> {
+    match op {
+        BatchOperation::Upsert(req) => {
+            let id = req.id.clone();
+            let (peer_id, created) = upsert_peer(conn, state, ab_guid, *req).await?;
+            changes.touch_peer(conn, peer_id).await?;
+            Ok(BatchResult {
+                op: "upsert",
+                id,
+                result: if created { "created" } else { "updated" },
+            })
+        }
+        BatchOperation::Delete { id, version } => {
+            let result = match check_peer_version(conn, ab_guid, &id, version).await? {
+                Some(peer) => {
+                    // Move to the trash, as a single delete does
+                    sqlx::query("UPDATE peers SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?")
+                        .bind(peer.id)
+                        .execute(&mut *conn)
+                        .await?;
+                    changes.delete_peer(conn, &peer).await?;
+                    "deleted"
+                }
+                None => "not_found",
+            };
+            Ok(BatchResult { op: "delete", id, result })
+        }
+        BatchOperation::AddTags { id, tags, version } => {
+            let peer = existing_peer(conn, ab_guid, &id, version).await?;
+            let changed = add_peer_tags(conn, ab_guid, peer.id, &tags).await?;
+            if changed {
+                changes.touch_peer(conn, peer.id).await?;
+            }
+            Ok(BatchResult {
+                op: "add_tags",
+                id,
+                result: if changed { "updated" } else { "unchanged" },
+            })
+        }
+        BatchOperation::RemoveTags { id, tags, version } => {
+            let peer = existing_peer(conn, ab_guid, &id, version).await?;
+            let changed = remove_peer_tags(conn, ab_guid, peer.id, &tags).await?;
+            if changed {
+                changes.touch_peer(conn, peer.id).await?;
+            }
+            Ok(BatchResult {
+                op: "remove_tags",
+                id,
+                result: if changed { "updated" } else { "unchanged" },
+            })
+        }
+        BatchOperation::SetNote { id, note, version } => {
+            let peer = existing_peer(conn, ab_guid, &id, version).await?;
+            let changed = peer.note != note;
+            if changed {
+                sqlx::query("UPDATE peers SET note = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+                    .bind(&note)
+                    .bind(peer.id)
+                    .execute(&mut *conn)
+                    .await?;
+                changes.touch_peer(conn, peer.id).await?;
+            }
+            Ok(BatchResult {
+                op: "set_note",
+                id,
+                result: if changed { "updated" } else { "unchanged" },
+            })
+        }
+    }
+}
+
+/// POST /api/ab/peers/batch/{guid} — apply a list of peer operations in one
+/// transaction. Operations run in order; if any fails nothing is applied and
+/// the error names the failing operation (counting from 0).
+async fn batch_peers(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    headers: HeaderMap,
+    Path(guid): Path<String>,
+    Json(req): Json<BatchRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let guid = resolve_writable_ab(&state.db, claims.user_id, &guid).await?;
+
+    if req.ops.len() > MAX_BATCH_OPS {
+        return Err(ApiError::BadRequest(format!(
+            "A batch is limited to {} operations",
+            MAX_BATCH_OPS
+        )));
+    }
+
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+    let quota = PeerQuota::load(&mut tx, &state.config, &guid).await?;
+    let mut changes = BookChanges::new(&guid, claims.user_id, "peer_batch");
+
+    let mut results = Vec::with_capacity(req.ops.len());
+    for (index, op) in req.ops.into_iter().enumerate() {
+        let result = apply_operation(&mut tx, &state, &mut changes, &guid, op)
+            .await
+            .map_err(|e| at_operation(index, e))?;
+        results.push(result);
+    }
+
+    quota.check(&mut tx).await?;
+    tx.commit().await?;
+
+    let total = results.len();
+    Ok(Json(json!({ "data": results, "total": total })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    async fn book_state(app: &TestApp) -> (i64, i64) {
+        sqlx::query_as(
+            "SELECT revision, (SELECT COUNT(*) FROM ab_history) FROM address_books WHERE guid = 'personal'",
+        )
+        .fetch_one(&app.state.db)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_failing_operation_rolls_back_the_batch() {
+        let app = TestApp::new().await;
+        app.call("POST", "/api/ab/tag/add/personal", Some(json!({ "name": "web" }))).await;
+        let peer = json!({ "id": "100", "note": "old" });
+        app.call("POST", "/api/ab/peer/add/personal", Some(peer)).await;
+        let before = book_state(&app).await;
+
+        let uri = "/api/ab/peers/batch/personal";
+        let batch = json!({ "ops": [
+            { "op": "upsert", "id": "200", "alias": "new" },
+            { "op": "set_note", "id": "100", "note": "new" },
+            { "op": "add_tags", "id": "100", "tags": ["missing"] },
+        ]});
+        let (status, body) = app.call("POST", uri, Some(batch)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "Operation 2: Tag 'missing' not found");
+
+        assert_eq!(book_state(&app).await, before);
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["data"][0]["note"], "old");
+
+        let batch = json!({ "ops": [
+            { "op": "upsert", "id": "200", "alias": "new" },
+            { "op": "set_note", "id": "100", "note": "new" },
+            { "op": "add_tags", "id": "100", "tags": ["web"] },
+            { "op": "delete", "id": "300" },
+        ]});
+        let (status, body) = app.call("POST", uri, Some(batch)).await;
+        assert_eq!(status, StatusCode::OK);
+        let results: Vec<&str> = body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["result"].as_str().unwrap())
+            .collect();
+        assert_eq!(results, ["created", "updated", "updated", "not_found"]);
+        // One revision for the whole batch
+        assert_eq!(book_state(&app).await.0, before.0 + 1);
+    }
+
+    #[tokio::test]
+    async fn batches_need_write_access() {
+        let app = TestApp::new().await;
+        let reader = app.add_user(2, "reader").await;
+        app.share(2, 1).await;
+
+        let batch = json!({ "ops": [{ "op": "upsert", "id": "200" }] });
+        let response = app
+            .send_as(&reader, "POST", "/api/ab/peers/batch/personal", &[], Some(batch.to_string()))
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(body["total"], 0);
+    }
+}
@@ -0,0 +1,442 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde_json::json;
+use sqlx::SqliteConnection;
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::address_book::*;
+use crate::models::peer::{Peer, PeerPayload};
+use crate::models::tag::{Tag, TagPayload};
+use crate::routes::history::{peer_snapshot, BookHistory};
+use crate::routes::peers::{resolve_ab_guid, tags_for_peers};
+use crate::routes::search::{reindex_peers, unindex_peer};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/api/ab/changes/{guid}", get(get_changes))
+}
+
+/// Revision bookkeeping for one write to an address book.
+///
+/// The book's revision is bumped at most once, on the first recorded change,
+/// so a request that ends up changing nothing leaves the revision alone.
+/// Every peer and tag touched by the write is stamped with that revision (and
+/// has its row version bumped) and deletions leave a tombstone behind for
+/// `GET /api/ab/changes/{guid}`. Each change is also passed on to
+/// `BookHistory` along with the acting user and `source`, the endpoint that
+/// made it.
+pub struct BookChanges {
+    ab_guid: String,
+    history: BookHistory,
+    revision: Option<i64>,
+}
+
+impl BookChanges {
+    pub fn new(ab_guid: &str, user_id: i64, source: &'static str) -> Self {
+        BookChanges {
+            ab_guid: ab_guid.to_string(),
+            history: BookHistory::new(ab_guid, user_id, source),
+            revision: None,
+        }
+    }
+
+    async fn revision(&mut self, conn: &mut SqliteConnection) -> Result<i64, ApiError> {
+        if let Some(revision) = self.revision {
+            return Ok(revision);
+        }
+
+        let revision: i64 = sqlx::query_scalar(
+            "UPDATE address_books SET revision = revision + 1, modified_at = ?
+             WHERE guid = ? RETURNING revision",
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .bind(&self.ab_guid)
+        .fetch_one(conn)
+        .await?;
+
+        self.revision = Some(revision);
+        Ok(revision)
+    }
+
+    /// Record that a peer was inserted or changed (including its tags).
+    pub async fn touch_peer(
+        &mut self,
+        conn: &mut SqliteConnection,
+        peer_id: i64,
+    ) -> Result<(), ApiError> {
+        let revision = self.revision(conn).await?;
+        let rustdesk_id: String = sqlx::query_scalar(
+            "UPDATE peers SET revision = ?, version = version + 1 WHERE id = ? RETURNING rustdesk_id",
+        )
+        .bind(revision)
+        .bind(peer_id)
+        .fetch_one(&mut *conn)
+        .await?;
+        reindex_peers(conn, "p.id = ?", peer_id).await?;
+        self.clear_tombstone(conn, "peer", &rustdesk_id).await?;
+
+        let snapshot = peer_snapshot(conn, peer_id).await?;
+        self.history
+            .record(conn, revision, "peer", peer_id, &rustdesk_id, Some(snapshot))
+            .await
+    }
+
+    /// Record that a tag was inserted or recoloured.
+    pub async fn touch_tag(
+        &mut self,
+        conn: &mut SqliteConnection,
+        tag_id: i64,
+    ) -> Result<(), ApiError> {
+        let revision = self.revision(conn).await?;
+        let (name, color): (String, i64) = sqlx::query_as(
+            "UPDATE tags SET revision = ?, version = version + 1 WHERE id = ? RETURNING name, color",
+        )
+        .bind(revision)
+        .bind(tag_id)
+        .fetch_one(&mut *conn)
+        .await?;
+        self.clear_tombstone(conn, "tag", &name).await?;
+
+        let snapshot = json!({ "name": name, "color": color });
+        self.history
+            .record(conn, revision, "tag", tag_id, &name, Some(snapshot))
+            .await
+    }
+
+    /// Record that a tag was renamed, which also changes every peer carrying it.
+    /// Clients that sync by name see the old name go away.
+    pub async fn rename_tag(
+        &mut self,
+        conn: &mut SqliteConnection,
+        tag_id: i64,
+        old_name: &str,
+    ) -> Result<(), ApiError> {
+        self.tombstone(conn, "tag", old_name).await?;
+        self.touch_tag(conn, tag_id).await?;
+
+        for peer_id in live_tagged_peers(conn, tag_id).await? {
+            self.touch_peer(conn, peer_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Take a tag off every peer carrying it, recording the change on those peers.
+    /// Peers in the trash lose the tag too but aren't recorded, so their
+    /// deletion stays visible to clients.
+    pub async fn untag_peers(
+        &mut self,
+        conn: &mut SqliteConnection,
+        tag_id: i64,
+    ) -> Result<(), ApiError> {
+        let peer_ids = live_tagged_peers(conn, tag_id).await?;
+        sqlx::query("DELETE FROM peer_tags WHERE tag_id = ?")
+            .bind(tag_id)
+            .execute(&mut *conn)
+            .await?;
+        for peer_id in peer_ids {
+            self.touch_peer(conn, peer_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Record that a peer was removed from the book.
+    pub async fn delete_peer(
+        &mut self,
+        conn: &mut SqliteConnection,
+        peer: &Peer,
+    ) -> Result<(), ApiError> {
+        unindex_peer(conn, peer.id).await?;
+        let revision = self.tombstone(conn, "peer", &peer.rustdesk_id).await?;
+        self.history
+            .record(conn, revision, "peer", peer.id, &peer.rustdesk_id, None)
+            .await
+    }
+
+    /// Record that a tag was removed from the book.
+    pub async fn delete_tag(
+        &mut self,
+        conn: &mut SqliteConnection,
+        tag: &Tag,
+    ) -> Result<(), ApiError> {
+        let revision = self.tombstone(conn, "tag", &tag.name).await?;
+        self.history
+            .record(conn, revision, "tag", tag.id, &tag.name, None)
+            .await
+    }
+
+    /// Leave a tombstone for a removed peer or tag, returning the revision.
+    async fn tombstone(
+        &mut self,
+        conn: &mut SqliteConnection,
+        kind: &str,
+        name: &str,
+    ) -> Result<i64, ApiError> {
+        let revision = self.revision(conn).await?;
+        sqlx::query(
+            "INSERT INTO ab_tombstones (ab_guid, kind, name, revision) VALUES (?, ?, ?, ?)
+             ON CONFLICT(ab_guid, kind, name) DO UPDATE SET revision = excluded.revision",
+        )
+        .bind(&self.ab_guid)
+        .bind(kind)
+        .bind(name)
+        .bind(revision)
+        .execute(conn)
+        .await?;
+        Ok(revision)
+    }
+
+    async fn clear_tombstone(
+        &self,
+        conn: &mut SqliteConnection,
+        kind: &str,
+        name: &str,
+    ) -> Result<(), ApiError> {
+        sqlx::query("DELETE FROM ab_tombstones WHERE ab_guid = ? AND kind = ? AND name = ?")
+            .bind(&self.ab_guid)
+            .bind(kind)
+            .bind(name)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Peers carrying a tag that are not in the trash.
+async fn live_tagged_peers(conn: &mut SqliteConnection, tag_id: i64) -> Result<Vec<i64>, ApiError> {
+    let peer_ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT pt.peer_id FROM peer_tags pt JOIN peers p ON p.id = pt.peer_id
+         WHERE pt.tag_id = ? AND p.deleted_at IS NULL",
+    )
+    .bind(tag_id)
+    .fetch_all(conn)
+    .await?;
+    Ok(peer_ids)
+}
+
+/// Current revision of an address book.
+pub async fn book_revision(conn: &mut SqliteConnection, ab_guid: &str) -> Result<i64, ApiError> {
+    let revision: i64 = sqlx::query_scalar("SELECT revision FROM address_books WHERE guid = ?")
+        .bind(ab_guid)
+        .fetch_one(conn)
+        .await?;
+    Ok(revision)
+}
+
+/// Strong ETag for an address book at a given revision.
+pub fn book_etag(ab_guid: &str, revision: i64) -> String {
+    format!("\"{}-{}\"", ab_guid, revision)
+}
+
+/// Strong ETag for a page of a book's peers. Pages that include saved
+/// passwords (sent to full-control users only) are a different representation
+/// of the same revision, so they get a tag of their own.
+pub fn peers_etag(ab_guid: &str, revision: i64, with_passwords: bool) -> String {
+    if with_passwords {
+        format!("\"{}-{}-p\"", ab_guid, revision)
+    } else {
+        book_etag(ab_guid, revision)
+    }
+}
+
+/// Strong ETag for an arbitrary response body (FNV-1a over its bytes), for
+/// responses that don't belong to a single book.
+pub fn content_etag(body: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in body {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("\"{:016x}\"", hash)
+}
+
+fn etag_matches(header_value: Option<&header::HeaderValue>, etag: &str) -> bool {
+    header_value
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            v.split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| tag == "*" || tag == etag)
+        })
+        .unwrap_or(false)
+}
+
+/// The `304 Not Modified` response when the client's `If-None-Match` already
+/// names `etag`.
+pub fn not_modified(headers: &HeaderMap, etag: &str) -> Option<Response> {
+    if !etag_matches(headers.get(header::IF_NONE_MATCH), etag) {
+        return None;
+    }
+    Some((StatusCode::NOT_MODIFIED, [(header::ETAG, etag.to_string())]).into_response())
+}
+
+/// Reject a write with `412 Precondition Failed` when the request carries an
+/// `If-Match` that doesn't match the book's current ETag. Call it inside the
+/// write's transaction so the check and the write see the same revision.
+pub async fn check_if_match(
+    conn: &mut SqliteConnection,
+    headers: &HeaderMap,
+    ab_guid: &str,
+) -> Result<(), ApiError> {
+    let Some(if_match) = headers.get(header::IF_MATCH) else {
+        return Ok(());
+    };
+
+    // Either representation of the current revision will do
+    let revision = book_revision(conn, ab_guid).await?;
+    if !etag_matches(Some(if_match), &book_etag(ab_guid, revision))
+        && !etag_matches(Some(if_match), &peers_etag(ab_guid, revision, true))
+    {
+        return Err(ApiError::PreconditionFailed(
+            "Address book has been modified".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Latest change time (unix seconds) across the address books visible to a
+/// device's assigned user, or 0 when the device has no user.
+pub async fn device_books_modified_at(
+    db: &sqlx::SqlitePool,
+    device_id: i64,
+) -> Result<i64, ApiError> {
+    let modified_at: i64 = sqlx::query_scalar(
+        "SELECT COALESCE(MAX(ab.modified_at), 0)
+         FROM address_books ab
+         JOIN devices d ON d.id = ?
+         WHERE ab.owner_id = d.user_id
+         OR EXISTS (
+             SELECT 1 FROM ab_shares s WHERE s.ab_guid = ab.guid AND (
+                 s.user_id = d.user_id
+                 OR s.group_id IN (SELECT group_id FROM user_groups WHERE user_id = d.user_id)
+             )
+         )",
+    )
+    .bind(device_id)
+    .fetch_one(db)
+    .await?;
+
+    Ok(modified_at)
+}
+
+/// GET /api/ab/changes/{guid}?since=N — peers and tags changed after revision
+/// `since`, plus the ones deleted since then. `since=0` returns the whole book.
+async fn get_changes(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(guid): Path<String>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<AbChangesResponse>, ApiError> {
+    let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
+
+    let mut tx = state.db.begin().await?;
+
+    let revision = book_revision(&mut tx, &guid).await?;
+
+    // A cursor from the future (e.g. a restored database) can't be trusted
+    let reset = query.since > revision;
+    let since = if reset { 0 } else { query.since };
+
+    let peers = sqlx::query_as::<_, Peer>(
+        "SELECT * FROM peers WHERE ab_guid = ? AND revision > ? AND deleted_at IS NULL ORDER BY rustdesk_id",
+    )
+    .bind(&guid)
+    .bind(since)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let tags = sqlx::query_as::<_, Tag>(
+        "SELECT * FROM tags WHERE ab_guid = ? AND revision > ? ORDER BY name",
+    )
+    .bind(&guid)
+    .bind(since)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let tombstones: Vec<(String, String)> = sqlx::query_as(
+        "SELECT kind, name FROM ab_tombstones WHERE ab_guid = ? AND revision > ? ORDER BY name",
+    )
+    .bind(&guid)
+    .bind(since)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let peer_ids: Vec<i64> = peers.iter().map(|p| p.id).collect();
+    let mut peer_tags = tags_for_peers(&mut tx, &peer_ids).await?;
+
+    tx.commit().await?;
+
+    let peers = peers
+        .into_iter()
+        .map(|peer| PeerPayload::new(&peer, peer_tags.remove(&peer.id).unwrap_or_default()))
+        .collect();
+    let tags = tags
+        .into_iter()
+        .map(|t| TagPayload {
+            name: t.name,
+            color: t.color,
+            version: t.version,
+        })
+        .collect();
+
+    let mut deleted_peers = Vec::new();
+    let mut deleted_tags = Vec::new();
+    for (kind, name) in tombstones {
+        match kind.as_str() {
+            "peer" => deleted_peers.push(name),
+            _ => deleted_tags.push(name),
+        }
+    }
+
+    Ok(Json(AbChangesResponse {
+        revision,
+        reset,
+        peers,
+        tags,
+        deleted_peers,
+        deleted_tags,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_support::{TestApp, BOOK};
+
+    #[tokio::test]
+    async fn deleting_a_tag_keeps_trashed_peers_deleted() {
+        let app = TestApp::new().await;
+        app.call("POST", "/api/ab/tag/add/personal", Some(json!({ "name": "web" }))).await;
+        app.call(
+            "POST",
+            "/api/ab/peer/add/personal",
+            Some(json!({ "id": "100", "tags": ["web"] })),
+        )
+        .await;
+        let (_, changes) = app.call("GET", "/api/ab/changes/personal?since=0", None).await;
+        let since = changes["revision"].as_i64().unwrap();
+
+        app.call("DELETE", "/api/ab/peer/personal", Some(json!({ "ids": ["100"] }))).await;
+        app.call("DELETE", "/api/ab/tag/personal", Some(json!({ "names": ["web"] }))).await;
+
+        let uri = format!("/api/ab/changes/{}?since={}", BOOK, since);
+        let (_, changes) = app.call("GET", &uri, None).await;
+        assert_eq!(changes["deleted_peers"], json!(["100"]));
+        assert_eq!(changes["deleted_tags"], json!(["web"]));
+        assert_eq!(changes["peers"], json!([]));
+
+        let actions: Vec<String> = sqlx::query_scalar(
+            "SELECT action FROM ab_history WHERE kind = 'peer' ORDER BY id",
+        )
+        .fetch_all(&app.state.db)
+        .await
+        .unwrap();
+        assert_eq!(actions, ["create", "delete"]);
+    }
+}
@@ -0,0 +1,314 @@
+use axum::{
+    extract::{Path, State},
+    routing::{get, put},
+    Json, Router,
+};
+use serde_json::{json, Map, Value};
+use sqlx::SqliteConnection;
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::custom_field::*;
+use crate::routes::changes::BookChanges;
+use crate::routes::peers::{require_full_control, resolve_ab_guid};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/ab/fields/{guid}", get(list_fields).post(create_field))
+        .route("/api/ab/fields/{guid}/{name}", put(update_field).delete(delete_field))
+}
+
+/// JSON path of a field inside `peers.custom_fields`.
+pub fn field_path(name: &str) -> String {
+    format!("$.\"{}\"", name)
+}
+
+pub async fn book_fields(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+) -> Result<Vec<CustomField>, ApiError> {
+    let fields = sqlx::query_as::<_, CustomField>(
+        "SELECT * FROM ab_custom_fields WHERE ab_guid = ? ORDER BY name",
+    )
+    .bind(ab_guid)
+    .fetch_all(conn)
+    .await?;
+    Ok(fields)
+}
+
+/// Check a value against the type of its field.
+pub fn check_value(field: &CustomField, value: &Value) -> Result<(), String> {
+    let valid = match (field.field_type.as_str(), value) {
+        ("string", Value::String(_)) => true,
+        ("number", Value::Number(_)) => true,
+        ("enum", Value::String(s)) => field.option_list().contains(s),
+        ("date", Value::String(s)) => chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok(),
+        _ => false,
+    };
+    if valid {
+        return Ok(());
+    }
+    Err(match field.field_type.as_str() {
+        "enum" => format!(
+            "Field '{}' must be one of: {}",
+            field.name,
+            field.option_list().join(", ")
+        ),
+        "date" => format!("Field '{}' must be a date (YYYY-MM-DD)", field.name),
+        other => format!("Field '{}' must be a {}", field.name, other),
+    })
+}
+
+/// Validate custom field values sent for a peer of `ab_guid`. Every key must
+/// be a field of the book; `null` is allowed and means "remove".
+pub async fn validate_custom_fields(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+    values: &Map<String, Value>,
+) -> Result<(), ApiError> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let fields = book_fields(conn, ab_guid).await?;
+    for (name, value) in values {
+        let Some(field) = fields.iter().find(|f| &f.name == name) else {
+            return Err(ApiError::BadRequest(format!("Unknown custom field '{}'", name)));
+        };
+        if !value.is_null() {
+            check_value(field, value).map_err(ApiError::BadRequest)?;
+        }
+    }
+    Ok(())
+}
+
+/// Keep the values that are valid for fields of `ab_guid`, for peers brought
+/// in from another book.
+pub async fn carry_custom_fields(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+    values: Map<String, Value>,
+) -> Result<Map<String, Value>, ApiError> {
+    if values.is_empty() {
+        return Ok(values);
+    }
+    let fields = book_fields(conn, ab_guid).await?;
+    Ok(values
+        .into_iter()
+        .filter(|(name, value)| {
+            fields
+                .iter()
+                .any(|f| &f.name == name && check_value(f, value).is_ok())
+        })
+        .collect())
+}
+
+pub fn check_field_name(name: &str) -> Result<(), ApiError> {
+    if name.is_empty()
+        || name.len() > 64
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == ' ')
+    {
+        return Err(ApiError::BadRequest(
+            "Field names are 1-64 letters, digits, spaces, '_' or '-'".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+pub fn check_definition(field_type: &str, options: &[String]) -> Result<(), ApiError> {
+    match field_type {
+        "string" | "number" | "date" if options.is_empty() => Ok(()),
+        "string" | "number" | "date" => Err(ApiError::BadRequest(
+            "Only enum fields take options".to_string(),
+        )),
+        "enum" if options.is_empty() || options.iter().any(|o| o.is_empty()) => Err(
+            ApiError::BadRequest("Enum fields need a list of non-empty options".to_string()),
+        ),
+        "enum" => Ok(()),
+        other => Err(ApiError::BadRequest(format!("Unknown field type '{}'", other))),
+    }
+}
+
+/// GET /api/ab/fields/{guid} — custom fields defined for a book.
+async fn list_fields(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(guid): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
+
+    let mut conn = state.db.acquire().await?;
+    let fields = book_fields(&mut conn, &guid).await?;
+
+    let items: Vec<CustomFieldPayload> = fields.iter().map(CustomFieldPayload::from).collect();
+    let total = items.len();
+    Ok(Json(json!({ "data": items, "total": total })))
+}
+
+/// POST /api/ab/fields/{guid}
+async fn create_field(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(guid): Path<String>,
+    Json(req): Json<CreateCustomFieldRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_full_control(&state.db, claims.user_id, &guid).await?;
+
+    let name = req.name.trim();
+    check_field_name(name)?;
+    check_definition(&req.field_type, &req.options)?;
+
+    let options = serde_json::to_string(&req.options).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let result = sqlx::query(
+        "INSERT OR IGNORE INTO ab_custom_fields (ab_guid, name, field_type, options) VALUES (?, ?, ?, ?)",
+    )
+    .bind(&guid)
+    .bind(name)
+    .bind(&req.field_type)
+    .bind(options)
+    .execute(&state.db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::Conflict(
+            format!("Field '{}' already exists", name),
+            None,
+        ));
+    }
+
+    Ok(Json(json!({})))
+}
+
+/// PUT /api/ab/fields/{guid}/{name} — replace the options of an enum field.
+/// Peers keep values that are no longer listed until they are next edited.
+async fn update_field(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path((guid, name)): Path<(String, String)>,
+    Json(req): Json<UpdateCustomFieldRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_full_control(&state.db, claims.user_id, &guid).await?;
+
+    let field_type: Option<String> = sqlx::query_scalar(
+        "SELECT field_type FROM ab_custom_fields WHERE ab_guid = ? AND name = ?",
+    )
+    .bind(&guid)
+    .bind(&name)
+    .fetch_optional(&state.db)
+    .await?;
+    let Some(field_type) = field_type else {
+        return Err(ApiError::NotFound(format!("Field '{}' not found", name)));
+    };
+    if field_type != "enum" {
+        return Err(ApiError::BadRequest("Only enum fields have options".to_string()));
+    }
+    check_definition(&field_type, &req.options)?;
+
+    let options = serde_json::to_string(&req.options).map_err(|e| ApiError::Internal(e.to_string()))?;
+    sqlx::query("UPDATE ab_custom_fields SET options = ? WHERE ab_guid = ? AND name = ?")
+        .bind(options)
+        .bind(&guid)
+        .bind(&name)
+        .execute(&state.db)
+        .await?;
+
+    Ok(Json(json!({})))
+}
+
+/// DELETE /api/ab/fields/{guid}/{name} — drop a field and its value from every peer.
+async fn delete_field(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path((guid, name)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    require_full_control(&state.db, claims.user_id, &guid).await?;
+
+    let mut tx = state.db.begin().await?;
+
+    let result = sqlx::query("DELETE FROM ab_custom_fields WHERE ab_guid = ? AND name = ?")
+        .bind(&guid)
+        .bind(&name)
+        .execute(&mut *tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("Field '{}' not found", name)));
+    }
+
+    // Trashed peers lose the value too, but only live ones are recorded as changed
+    let path = field_path(&name);
+    let peer_ids: Vec<i64> = sqlx::query_scalar::<_, Option<i64>>(
+        "UPDATE peers SET custom_fields = json_remove(custom_fields, ?)
+         WHERE ab_guid = ? AND json_type(custom_fields, ?) IS NOT NULL
+         RETURNING CASE WHEN deleted_at IS NULL THEN id END",
+    )
+    .bind(&path)
+    .bind(&guid)
+    .bind(&path)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let mut changes = BookChanges::new(&guid, claims.user_id, "field_delete");
+    for peer_id in peer_ids {
+        changes.touch_peer(&mut tx, peer_id).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(json!({})))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    /// Status and listed RustDesk IDs of a peer listing.
+    async fn listed(app: &TestApp, params: &str) -> (StatusCode, Vec<String>) {
+        let uri = format!("/api/ab/peers?ab=personal&{}", params);
+        let (status, body) = app.call("GET", &uri, None).await;
+        let ids = body["data"]
+            .as_array()
+            .map(|peers| peers.iter().map(|p| p["id"].as_str().unwrap().to_string()).collect())
+            .unwrap_or_default();
+        (status, ids)
+    }
+
+    #[tokio::test]
+    async fn peer_listings_filter_by_custom_fields() {
+        let app = TestApp::new().await;
+        let fields = [
+            json!({ "name": "site", "type": "string" }),
+            json!({ "name": "rack", "type": "number" }),
+        ];
+        for field in fields {
+            let (status, _) = app.call("POST", "/api/ab/fields/personal", Some(field)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let peers = [
+            json!({ "id": "100", "custom_fields": { "site": "Berlin", "rack": 4 } }),
+            json!({ "id": "200", "custom_fields": { "site": "Berlin", "rack": 7 } }),
+            json!({ "id": "300", "custom_fields": { "site": "Paris" } }),
+        ];
+        for peer in peers {
+            let (status, _) = app.call("POST", "/api/ab/peer/add/personal", Some(peer)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let (status, ids) = listed(&app, "field.site=Berlin").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ids, ["100", "200"]);
+        let (_, ids) = listed(&app, "field.site=Berlin&field.rack=7").await;
+        assert_eq!(ids, ["200"]);
+
+        let (status, _) = listed(&app, "field.rack=seven").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = listed(&app, "field.owner=me").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
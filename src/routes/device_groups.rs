@@ -0,0 +1,173 @@
+use axum::{
+    extract::{Path, State},
+    routing::{get, put},
+    Json, Router,
+};
+use serde_json::{json, Value};
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::device_group::*;
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/device-groups", get(list_device_groups).post(create_device_group))
+        .route(
+            "/api/device-groups/{id}",
+            put(update_device_group).delete(delete_device_group),
+        )
+}
+
+fn require_admin(claims: &crate::auth::jwt::Claims) -> Result<(), ApiError> {
+    if !claims.is_admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+    Ok(())
+}
+
+/// Replace the set of user groups that can see a device group.
+async fn set_user_groups(
+    db: &sqlx::SqlitePool,
+    device_group_id: i64,
+    user_groups: &[i64],
+) -> Result<(), ApiError> {
+    sqlx::query("DELETE FROM device_group_access WHERE device_group_id = ?")
+        .bind(device_group_id)
+        .execute(db)
+        .await?;
+
+    for group_id in user_groups {
+        sqlx::query(
+            "INSERT OR IGNORE INTO device_group_access (device_group_id, group_id)
+             SELECT ?, id FROM groups WHERE id = ?",
+        )
+        .bind(device_group_id)
+        .bind(group_id)
+        .execute(db)
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn list_device_groups(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let groups = sqlx::query_as::<_, DeviceGroup>("SELECT * FROM device_groups ORDER BY id")
+        .fetch_all(&state.db)
+        .await?;
+
+    let mut items = Vec::new();
+    for g in groups {
+        let user_groups: Vec<i64> = sqlx::query_scalar(
+            "SELECT group_id FROM device_group_access WHERE device_group_id = ? ORDER BY group_id",
+        )
+        .bind(g.id)
+        .fetch_all(&state.db)
+        .await?;
+
+        let device_count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM devices WHERE device_group_id = ?")
+                .bind(g.id)
+                .fetch_one(&state.db)
+                .await?;
+
+        items.push(DeviceGroupListItem {
+            id: g.id,
+            name: g.name,
+            note: g.note,
+            user_groups,
+            device_count,
+            created_at: g.created_at,
+        });
+    }
+
+    let total = items.len();
+    Ok(Json(json!({ "data": items, "total": total })))
+}
+
+async fn create_device_group(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<CreateDeviceGroupRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let result = sqlx::query("INSERT INTO device_groups (name, note) VALUES (?, ?)")
+        .bind(&req.name)
+        .bind(&req.note)
+        .execute(&state.db)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_err) if db_err.message().contains("UNIQUE") => {
+                ApiError::Conflict(format!("Device group '{}' already exists", req.name), None)
+            }
+            _ => ApiError::Internal(e.to_string()),
+        })?;
+
+    set_user_groups(&state.db, result.last_insert_rowid(), &req.user_groups).await?;
+
+    Ok(Json(json!({})))
+}
+
+async fn update_device_group(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+    Json(req): Json<Value>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    if let Some(name) = req.get("name").and_then(|v| v.as_str()) {
+        sqlx::query("UPDATE device_groups SET name = ? WHERE id = ?")
+            .bind(name)
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+    }
+    if let Some(note) = req.get("note").and_then(|v| v.as_str()) {
+        sqlx::query("UPDATE device_groups SET note = ? WHERE id = ?")
+            .bind(note)
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+    }
+    if let Some(user_groups) = req.get("user_groups").and_then(|v| v.as_array()) {
+        let ids: Vec<i64> = user_groups.iter().filter_map(|v| v.as_i64()).collect();
+        set_user_groups(&state.db, id, &ids).await?;
+    }
+
+    Ok(Json(json!({})))
+}
+
+async fn delete_device_group(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    // Unassign member devices first so they don't point at a missing group
+    sqlx::query("UPDATE devices SET device_group_id = NULL WHERE device_group_id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM device_group_access WHERE device_group_id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM strategy_assignments WHERE device_group_id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM device_groups WHERE id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+
+    Ok(Json(json!({})))
+}
@@ -0,0 +1,377 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::auth::middleware::AuthUser;
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::models::device::*;
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/devices", get(list_devices))
+        .route("/api/devices/{id}", put(update_device).delete(delete_device))
+        .route(
+            "/api/devices/{id}/commands",
+            get(list_commands).post(enqueue_command),
+        )
+        .route("/api/devices/{id}/sysinfo-history", get(sysinfo_history))
+        .route("/api/devices/{id}/approve", post(approve_device))
+        .route("/api/devices/{id}/reject", post(reject_device))
+}
+
+fn require_admin(claims: &crate::auth::jwt::Claims) -> Result<(), ApiError> {
+    if !claims.is_admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DevicesQuery {
+    /// Only list devices in this enrollment state.
+    #[serde(default)]
+    pub status: String,
+}
+
+/// Enrollment state given to a device seen for the first time.
+pub fn new_device_status(config: &Config) -> &'static str {
+    if config.device_approval {
+        "pending"
+    } else {
+        "approved"
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EnrollmentQuery {
+    #[serde(default)]
+    pub token: String,
+}
+
+/// Look up the reporting device, enrolling it when it is seen for the first time.
+/// Returns the device id and enrollment status, or `None` when an unknown device
+/// doesn't present the configured enrollment token.
+pub async fn admit_device(
+    state: &AppState,
+    rustdesk_id: &str,
+    uuid: &str,
+    headers: &HeaderMap,
+    query_token: &str,
+) -> Result<Option<(i64, String)>, ApiError> {
+    let known: Option<(i64, String)> =
+        sqlx::query_as("SELECT id, status FROM devices WHERE rustdesk_id = ?")
+            .bind(rustdesk_id)
+            .fetch_optional(&state.db)
+            .await?;
+    if known.is_some() {
+        return Ok(known);
+    }
+
+    if !state.config.enrollment_token.is_empty() {
+        let token = headers
+            .get("X-Enrollment-Token")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or(query_token);
+        if token != state.config.enrollment_token {
+            tracing::debug!("Ignoring device {} without a valid enrollment token", rustdesk_id);
+            return Ok(None);
+        }
+    }
+
+    sqlx::query(
+        "INSERT INTO devices (rustdesk_id, uuid, status) VALUES (?, ?, ?)
+         ON CONFLICT(rustdesk_id) DO NOTHING",
+    )
+    .bind(rustdesk_id)
+    .bind(uuid)
+    .bind(new_device_status(&state.config))
+    .execute(&state.db)
+    .await?;
+
+    let device: (i64, String) =
+        sqlx::query_as("SELECT id, status FROM devices WHERE rustdesk_id = ?")
+            .bind(rustdesk_id)
+            .fetch_one(&state.db)
+            .await?;
+    Ok(Some(device))
+}
+
+/// Assign an unowned device to the user logged in on it. Only devices that
+/// were admitted (see `admit_device`) and aren't rejected should be claimed.
+/// Devices already assigned (by an admin or an earlier login) are left alone.
+pub async fn claim_device(
+    db: &sqlx::SqlitePool,
+    device_id: i64,
+    user_id: i64,
+) -> Result<(), ApiError> {
+    sqlx::query("UPDATE devices SET user_id = COALESCE(user_id, ?) WHERE id = ?")
+        .bind(user_id)
+        .bind(device_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// GET /api/devices — admins see every device, other users only the devices
+/// assigned to them or to a device group shared with one of their groups.
+async fn list_devices(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<DevicesQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let items = sqlx::query_as::<_, DeviceListItem>(
+        "SELECT d.id, d.rustdesk_id, d.hostname, d.platform, d.os, d.version,
+                d.user_id, u.username, d.device_group_id, g.name AS device_group_name,
+                d.status, d.online, d.last_online, d.created_at
+         FROM devices d
+         LEFT JOIN users u ON u.id = d.user_id
+         LEFT JOIN device_groups g ON g.id = d.device_group_id
+         WHERE (? OR d.user_id = ? OR d.device_group_id IN (
+             SELECT a.device_group_id FROM device_group_access a
+             JOIN user_groups ug ON ug.group_id = a.group_id
+             WHERE ug.user_id = ?
+         ))
+         AND (? = '' OR d.status = ?)
+         ORDER BY d.rustdesk_id",
+    )
+    .bind(claims.is_admin)
+    .bind(claims.user_id)
+    .bind(claims.user_id)
+    .bind(&query.status)
+    .bind(&query.status)
+    .fetch_all(&state.db)
+    .await?;
+
+    let total = items.len();
+    Ok(Json(json!({ "data": items, "total": total })))
+}
+
+/// PUT /api/devices/{id} — assign a device to a user and/or device group.
+/// Passing `null` clears the assignment.
+async fn update_device(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+    Json(req): Json<Value>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM devices WHERE id = ?")
+        .bind(id)
+        .fetch_one(&state.db)
+        .await?;
+    if !exists {
+        return Err(ApiError::NotFound("Device not found".to_string()));
+    }
+
+    if let Some(user_id) = req.get("user_id") {
+        let user_id = user_id.as_i64();
+        if let Some(user_id) = user_id {
+            let user_exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM users WHERE id = ?")
+                .bind(user_id)
+                .fetch_one(&state.db)
+                .await?;
+            if !user_exists {
+                return Err(ApiError::NotFound("User not found".to_string()));
+            }
+        }
+        sqlx::query("UPDATE devices SET user_id = ? WHERE id = ?")
+            .bind(user_id)
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+    }
+    if let Some(group_id) = req.get("device_group_id") {
+        let group_id = group_id.as_i64();
+        if let Some(group_id) = group_id {
+            let group_exists: bool =
+                sqlx::query_scalar("SELECT COUNT(*) > 0 FROM device_groups WHERE id = ?")
+                    .bind(group_id)
+                    .fetch_one(&state.db)
+                    .await?;
+            if !group_exists {
+                return Err(ApiError::NotFound("Device group not found".to_string()));
+            }
+        }
+        sqlx::query("UPDATE devices SET device_group_id = ? WHERE id = ?")
+            .bind(group_id)
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+    }
+
+    Ok(Json(json!({})))
+}
+
+async fn delete_device(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    sqlx::query("DELETE FROM device_commands WHERE device_id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM strategy_assignments WHERE device_id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM device_sysinfo_history WHERE device_id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM devices WHERE id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+
+    Ok(Json(json!({})))
+}
+
+/// GET /api/devices/{id}/commands — command queue of a device, newest first.
+async fn list_commands(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let commands = sqlx::query_as::<_, DeviceCommand>(
+        "SELECT * FROM device_commands WHERE device_id = ? ORDER BY id DESC",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+
+    let total = commands.len();
+    Ok(Json(json!({ "data": commands, "total": total })))
+}
+
+/// POST /api/devices/{id}/commands — queue a command for the next heartbeat.
+async fn enqueue_command(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+    Json(req): Json<EnqueueCommandRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let payload = match req.command.as_str() {
+        "sysinfo" => json!({}),
+        "disconnect" => {
+            if req.conns.is_empty() {
+                return Err(ApiError::BadRequest("No connections to disconnect".to_string()));
+            }
+            json!({ "conns": req.conns })
+        }
+        "strategy" => json!({
+            "config_options": req.options,
+            // Echoed back by the client in its next heartbeat to acknowledge
+            "modified_at": chrono::Utc::now().timestamp(),
+        }),
+        other => {
+            return Err(ApiError::BadRequest(format!("Unknown command '{}'", other)));
+        }
+    };
+
+    let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM devices WHERE id = ?")
+        .bind(id)
+        .fetch_one(&state.db)
+        .await?;
+    if !exists {
+        return Err(ApiError::NotFound("Device not found".to_string()));
+    }
+
+    let result = sqlx::query(
+        "INSERT INTO device_commands (device_id, command, payload, created_by) VALUES (?, ?, ?, ?)",
+    )
+    .bind(id)
+    .bind(&req.command)
+    .bind(payload.to_string())
+    .bind(claims.user_id)
+    .execute(&state.db)
+    .await?;
+
+    Ok(Json(json!({ "id": result.last_insert_rowid() })))
+}
+
+/// GET /api/devices/{id}/sysinfo-history — recorded hostname/OS/CPU/memory/version
+/// changes of a device, newest first.
+async fn sysinfo_history(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let history = sqlx::query_as::<_, DeviceSysinfoHistory>(
+        "SELECT * FROM device_sysinfo_history WHERE device_id = ? ORDER BY id DESC",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+
+    let total = history.len();
+    Ok(Json(json!({ "data": history, "total": total })))
+}
+
+/// Move a device to a new enrollment state and record the decision in the audit log.
+async fn set_device_status(
+    db: &sqlx::SqlitePool,
+    claims: &crate::auth::jwt::Claims,
+    id: i64,
+    status: &str,
+) -> Result<(), ApiError> {
+    let rustdesk_id: String = sqlx::query_scalar("SELECT rustdesk_id FROM devices WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Device not found".to_string()))?;
+
+    sqlx::query("UPDATE devices SET status = ? WHERE id = ?")
+        .bind(status)
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    sqlx::query("INSERT INTO audit_log (user_id, action, rustdesk_id) VALUES (?, ?, ?)")
+        .bind(claims.user_id)
+        .bind(format!("device_{}", status))
+        .bind(&rustdesk_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// POST /api/devices/{id}/approve — let a pending (or rejected) device report in.
+async fn approve_device(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+    set_device_status(&state.db, &claims, id, "approved").await?;
+    Ok(Json(json!({})))
+}
+
+/// POST /api/devices/{id}/reject — ignore heartbeats and sysinfo from this device.
+async fn reject_device(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+    set_device_status(&state.db, &claims, id, "rejected").await?;
+    Ok(Json(json!({})))
+}
@@ -0,0 +1,223 @@
+use axum::{
+    extract::{Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::peer::*;
+use crate::routes::changes::BookChanges;
+use crate::routes::custom_fields::carry_custom_fields;
+use crate::routes::peers::{carry_tags, set_peer_tags, tags_for_peers};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/ab/duplicates", get(list_duplicates))
+        .route("/api/ab/duplicates/merge", post(merge_peers))
+}
+
+fn require_admin(claims: &crate::auth::jwt::Claims) -> Result<(), ApiError> {
+    if !claims.is_admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+    Ok(())
+}
+
+/// GET /api/ab/duplicates — live peers that appear more than once across all
+/// address books, grouped by RustDesk ID or (case-insensitively) by hostname.
+async fn list_duplicates(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<DuplicatesQuery>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let key = match query.by.as_str() {
+        "" | "id" => "p.rustdesk_id",
+        "hostname" => "LOWER(p.hostname)",
+        other => return Err(ApiError::BadRequest(format!("Unknown grouping '{}'", other))),
+    };
+
+    let mut tx = state.db.begin().await?;
+
+    let rows: Vec<(String, String, String, i64)> = sqlx::query_as(&format!(
+        "SELECT {key}, ab.guid, ab.name, p.id FROM peers p
+         JOIN address_books ab ON ab.guid = p.ab_guid
+         WHERE p.deleted_at IS NULL AND {key} != '' AND {key} IN (
+             SELECT {key} FROM peers p WHERE p.deleted_at IS NULL
+             GROUP BY {key} HAVING COUNT(*) > 1
+         )
+         ORDER BY {key}, ab.name, p.id"
+    ))
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let peer_ids: Vec<i64> = rows.iter().map(|r| r.3).collect();
+    let ids = serde_json::to_string(&peer_ids).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let peers = sqlx::query_as::<_, Peer>(
+        "SELECT * FROM peers WHERE id IN (SELECT value FROM json_each(?))",
+    )
+    .bind(ids)
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut tags = tags_for_peers(&mut tx, &peer_ids).await?;
+
+    tx.commit().await?;
+
+    let mut peers: HashMap<i64, Peer> = peers.into_iter().map(|p| (p.id, p)).collect();
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    for (key, ab_guid, ab_name, peer_id) in rows {
+        let Some(peer) = peers.remove(&peer_id) else {
+            continue;
+        };
+        let entry = DuplicatePeer {
+            ab_guid,
+            ab_name,
+            peer: PeerPayload::new(&peer, tags.remove(&peer.id).unwrap_or_default()),
+        };
+        match groups.last_mut() {
+            Some(group) if group.key == key => group.peers.push(entry),
+            _ => groups.push(DuplicateGroup { key, peers: vec![entry] }),
+        }
+    }
+
+    let total = groups.len();
+    Ok(Json(json!({ "data": groups, "total": total })))
+}
+
+/// POST /api/ab/duplicates/merge — fold duplicate peers into a canonical entry.
+/// Empty fields of the canonical peer are filled from the others (in the order
+/// given), distinct notes are appended, and it ends up with the union of all
+/// their tags. The other entries are moved to their book's trash.
+async fn merge_peers(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<MergePeersRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let mut tx = state.db.begin().await?;
+
+    let find = |peer_ref: &PeerRef| {
+        sqlx::query_as::<_, Peer>(
+            "SELECT * FROM peers WHERE ab_guid = ? AND rustdesk_id = ? AND deleted_at IS NULL",
+        )
+        .bind(peer_ref.ab_guid.clone())
+        .bind(peer_ref.id.clone())
+    };
+
+    let mut canonical = find(&req.canonical)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Peer '{}' not found", req.canonical.id)))?;
+
+    let mut others = Vec::new();
+    for peer_ref in &req.others {
+        let peer = find(peer_ref)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| ApiError::NotFound(format!("Peer '{}' not found", peer_ref.id)))?;
+        if peer.id != canonical.id && !others.iter().any(|o: &Peer| o.id == peer.id) {
+            others.push(peer);
+        }
+    }
+    if others.is_empty() {
+        return Err(ApiError::BadRequest("Nothing to merge".to_string()));
+    }
+
+    let mut custom_fields = canonical.custom_field_values();
+    let mut incoming = serde_json::Map::new();
+    for other in &others {
+        for (field, value) in [
+            (&mut canonical.hash, &other.hash),
+            (&mut canonical.username, &other.username),
+            (&mut canonical.hostname, &other.hostname),
+            (&mut canonical.platform, &other.platform),
+            (&mut canonical.alias, &other.alias),
+            (&mut canonical.rdp_port, &other.rdp_port),
+            (&mut canonical.rdp_username, &other.rdp_username),
+            (&mut canonical.login_name, &other.login_name),
+            (&mut canonical.device_group_name, &other.device_group_name),
+        ] {
+            if field.is_empty() {
+                field.clone_from(value);
+            }
+        }
+        // Saved passwords are bound to their peer, so re-encrypt the one taken over
+        if canonical.password.is_empty() {
+            canonical.password = state.peer_keys.reseal(
+                &other.password,
+                (&other.ab_guid, &other.rustdesk_id),
+                (&canonical.ab_guid, &canonical.rustdesk_id),
+            )?;
+        }
+        for (name, value) in other.custom_field_values() {
+            if !custom_fields.contains_key(&name) {
+                incoming.entry(name).or_insert(value);
+            }
+        }
+        if !other.note.is_empty() && !canonical.note.contains(other.note.as_str()) {
+            if !canonical.note.is_empty() {
+                canonical.note.push('\n');
+            }
+            canonical.note.push_str(&other.note);
+        }
+    }
+
+    // Values taken from peers of other books must fit this book's fields
+    custom_fields.extend(carry_custom_fields(&mut tx, &canonical.ab_guid, incoming).await?);
+
+    sqlx::query(
+        "UPDATE peers SET hash = ?, username = ?, hostname = ?, platform = ?, alias = ?, note = ?,
+             password = ?, rdp_port = ?, rdp_username = ?, login_name = ?, device_group_name = ?,
+             custom_fields = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?",
+    )
+    .bind(&canonical.hash)
+    .bind(&canonical.username)
+    .bind(&canonical.hostname)
+    .bind(&canonical.platform)
+    .bind(&canonical.alias)
+    .bind(&canonical.note)
+    .bind(&canonical.password)
+    .bind(&canonical.rdp_port)
+    .bind(&canonical.rdp_username)
+    .bind(&canonical.login_name)
+    .bind(&canonical.device_group_name)
+    .bind(Value::Object(custom_fields).to_string())
+    .bind(canonical.id)
+    .execute(&mut *tx)
+    .await?;
+
+    let mut changes: HashMap<String, BookChanges> = HashMap::new();
+    let mut canonical_changes = BookChanges::new(&canonical.ab_guid, claims.user_id, "merge");
+
+    let mut peer_ids: Vec<i64> = vec![canonical.id];
+    peer_ids.extend(others.iter().map(|o| o.id));
+    let names = carry_tags(&mut tx, &mut canonical_changes, &canonical.ab_guid, &peer_ids).await?;
+    set_peer_tags(&mut tx, &canonical.ab_guid, canonical.id, &names).await?;
+    canonical_changes.touch_peer(&mut tx, canonical.id).await?;
+
+    for other in &others {
+        sqlx::query("UPDATE peers SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(other.id)
+            .execute(&mut *tx)
+            .await?;
+        let book = if other.ab_guid == canonical.ab_guid {
+            &mut canonical_changes
+        } else {
+            changes
+                .entry(other.ab_guid.clone())
+                .or_insert_with(|| BookChanges::new(&other.ab_guid, claims.user_id, "merge"))
+        };
+        book.delete_peer(&mut tx, other).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(json!({ "merged": others.len() })))
+}
@@ -61,7 +61,7 @@ async fn create_group(
         .await
         .map_err(|e| match e {
             sqlx::Error::Database(ref db_err) if db_err.message().contains("UNIQUE") => {
-                ApiError::Conflict(format!("Group '{}' already exists", req.name))
+                ApiError::Conflict(format!("Group '{}' already exists", req.name), None)
             }
             _ => ApiError::Internal(e.to_string()),
         })?;
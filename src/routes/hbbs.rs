@@ -0,0 +1,152 @@
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection, SqliteConnection};
+use std::collections::HashSet;
+
+use crate::auth::middleware::AuthUser;
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::models::hbbs::*;
+use crate::routes::changes::BookChanges;
+use crate::routes::devices::new_device_status;
+use crate::routes::peers::PeerQuota;
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/ab/hbbs/peers", get(list_hbbs_peers))
+        .route("/api/ab/hbbs/import", post(import_hbbs_peers))
+}
+
+fn require_admin(claims: &crate::auth::jwt::Claims) -> Result<(), ApiError> {
+    if !claims.is_admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+    Ok(())
+}
+
+/// Every ID registered with hbbs. The database is opened read-only for the
+/// duration of the call, so hbbs keeps owning it.
+async fn hbbs_peers(config: &Config) -> Result<Vec<HbbsPeer>, ApiError> {
+    if config.hbbs_db_path.is_empty() {
+        return Err(ApiError::NotFound("hbbs_db_path is not configured".to_string()));
+    }
+
+    let mut conn = SqliteConnectOptions::new()
+        .filename(&config.hbbs_db_path)
+        .read_only(true)
+        .connect()
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to open the hbbs database: {}", e)))?;
+
+    let peers = sqlx::query_as::<_, HbbsPeer>(
+        "SELECT id, uuid, CAST(created_at AS TEXT) AS created_at, info FROM peer ORDER BY id",
+    )
+    .fetch_all(&mut conn)
+    .await?;
+    conn.close().await?;
+
+    Ok(peers)
+}
+
+/// hbbs IDs that are not a live peer of any address book.
+async fn unassigned_peers(
+    conn: &mut SqliteConnection,
+    config: &Config,
+) -> Result<Vec<HbbsPeer>, ApiError> {
+    let known: HashSet<String> =
+        sqlx::query_scalar("SELECT DISTINCT rustdesk_id FROM peers WHERE deleted_at IS NULL")
+            .fetch_all(conn)
+            .await?
+            .into_iter()
+            .collect();
+
+    let mut peers = hbbs_peers(config).await?;
+    peers.retain(|p| !p.id.is_empty() && !known.contains(&p.id));
+    Ok(peers)
+}
+
+/// GET /api/ab/hbbs/peers — IDs registered with hbbs that no address book holds yet.
+async fn list_hbbs_peers(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let mut conn = state.db.acquire().await?;
+    let peers = unassigned_peers(&mut conn, &state.config).await?;
+
+    let items: Vec<HbbsPeerPayload> = peers.iter().map(HbbsPeerPayload::from).collect();
+    let total = items.len();
+    Ok(Json(json!({ "data": items, "total": total })))
+}
+
+/// POST /api/ab/hbbs/import — add hbbs IDs that no address book holds yet to
+/// the given book, and optionally create their devices. Requested IDs that
+/// hbbs doesn't know or that are already in a book are skipped.
+async fn import_hbbs_peers(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<HbbsImportRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let mut tx = state.db.begin().await?;
+
+    let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM address_books WHERE guid = ?")
+        .bind(&req.ab)
+        .fetch_one(&mut *tx)
+        .await?;
+    if !exists {
+        return Err(ApiError::NotFound("Address book not found".to_string()));
+    }
+
+    let mut peers = unassigned_peers(&mut tx, &state.config).await?;
+    if !req.ids.is_empty() {
+        peers.retain(|p| req.ids.contains(&p.id));
+    }
+
+    let quota = PeerQuota::load(&mut tx, &state.config, &req.ab).await?;
+    let mut changes = BookChanges::new(&req.ab, claims.user_id, "hbbs_import");
+
+    let mut devices_added = 0;
+    for peer in &peers {
+        // A copy of the ID in this book's trash is restored
+        let peer_id: i64 = sqlx::query_scalar(
+            "INSERT INTO peers (ab_guid, rustdesk_id) VALUES (?, ?)
+             ON CONFLICT(ab_guid, rustdesk_id) DO UPDATE SET
+                 deleted_at = NULL,
+                 updated_at = CURRENT_TIMESTAMP
+             RETURNING id",
+        )
+        .bind(&req.ab)
+        .bind(&peer.id)
+        .fetch_one(&mut *tx)
+        .await?;
+        changes.touch_peer(&mut tx, peer_id).await?;
+
+        if req.devices {
+            let result = sqlx::query(
+                "INSERT INTO devices (rustdesk_id, uuid, status) VALUES (?, ?, ?)
+                 ON CONFLICT(rustdesk_id) DO NOTHING",
+            )
+            .bind(&peer.id)
+            .bind(STANDARD.encode(&peer.uuid))
+            .bind(new_device_status(&state.config))
+            .execute(&mut *tx)
+            .await?;
+            devices_added += result.rows_affected();
+        }
+    }
+
+    quota.check(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({ "added": peers.len(), "devices_added": devices_added })))
+}
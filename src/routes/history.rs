@@ -0,0 +1,389 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection};
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::history::*;
+use crate::models::peer::Peer;
+use crate::routes::changes::{check_if_match, BookChanges};
+use crate::routes::peers::{
+    resolve_ab_guid, resolve_writable_ab, set_peer_tags, tags_for_peers, PeerQuota,
+};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/ab/history/{guid}", get(get_book_history))
+        .route("/api/ab/history/{guid}/peer/{id}", get(get_peer_history))
+        .route("/api/ab/history/{guid}/revert/{history_id}", post(revert_peer))
+}
+
+/// Writes the `ab_history` entries of one write to an address book, on behalf
+/// of `BookChanges`, which hands over every peer and tag it records.
+pub struct BookHistory {
+    ab_guid: String,
+    user_id: i64,
+    source: &'static str,
+}
+
+impl BookHistory {
+    pub fn new(ab_guid: &str, user_id: i64, source: &'static str) -> Self {
+        BookHistory {
+            ab_guid: ab_guid.to_string(),
+            user_id,
+            source,
+        }
+    }
+
+    /// Append a history entry for a row changed at `revision`, taking the old
+    /// value from the row's previous entry. `new_value` is `None` for
+    /// deletions. Nothing is written when the row ends up exactly as it was.
+    pub async fn record(
+        &self,
+        conn: &mut SqliteConnection,
+        revision: i64,
+        kind: &str,
+        row_id: i64,
+        name: &str,
+        new_value: Option<Value>,
+    ) -> Result<(), ApiError> {
+        let previous: Option<(String, Option<String>)> = sqlx::query_as(
+            "SELECT action, new_value FROM ab_history WHERE kind = ? AND row_id = ?
+             ORDER BY id DESC LIMIT 1",
+        )
+        .bind(kind)
+        .bind(row_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let (previous_action, old_value) = match previous {
+            Some((action, value)) => (
+                Some(action),
+                value.and_then(|v| serde_json::from_str::<Value>(&v).ok()),
+            ),
+            None => (None, None),
+        };
+        if new_value == old_value {
+            return Ok(());
+        }
+
+        let action = match (&new_value, previous_action.as_deref()) {
+            (None, _) => "delete",
+            (Some(_), None) => "create",
+            (Some(_), Some("delete")) => "restore",
+            (Some(_), Some(_)) => "update",
+        };
+
+        sqlx::query(
+            "INSERT INTO ab_history
+                 (ab_guid, kind, row_id, name, action, old_value, new_value, user_id, source, revision)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&self.ab_guid)
+        .bind(kind)
+        .bind(row_id)
+        .bind(name)
+        .bind(action)
+        .bind(old_value.map(|v| v.to_string()))
+        .bind(new_value.map(|v| v.to_string()))
+        .bind(self.user_id)
+        .bind(self.source)
+        .bind(revision)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// The fields of a peer tracked by history; `hash` and `password` are left out
+/// as they are credentials.
+pub async fn peer_snapshot(conn: &mut SqliteConnection, peer_id: i64) -> Result<Value, ApiError> {
+    let peer = sqlx::query_as::<_, Peer>("SELECT * FROM peers WHERE id = ?")
+        .bind(peer_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let mut tags = tags_for_peers(conn, &[peer_id]).await?;
+
+    Ok(json!({
+        "id": peer.rustdesk_id,
+        "username": peer.username,
+        "hostname": peer.hostname,
+        "platform": peer.platform,
+        "alias": peer.alias,
+        "note": peer.note,
+        "tags": tags.remove(&peer_id).unwrap_or_default(),
+        "forceAlwaysRelay": peer.force_always_relay,
+        "rdpPort": peer.rdp_port,
+        "rdpUsername": peer.rdp_username,
+        "loginName": peer.login_name,
+        "device_group_name": peer.device_group_name,
+        "same_server": peer.same_server,
+        "custom_fields": peer.custom_field_values(),
+        "extra": peer.extra_fields(),
+    }))
+}
+
+/// Run a history listing for a book, newest first, optionally limited to one
+/// peer by RustDesk ID.
+async fn history_page(
+    state: &AppState,
+    ab_guid: &str,
+    rustdesk_id: Option<&str>,
+    query: &HistoryQuery,
+) -> Result<Json<Value>, ApiError> {
+    let offset = if query.current > 0 {
+        (query.current - 1) * query.pageSize
+    } else {
+        0
+    };
+
+    let push_filters = |qb: &mut QueryBuilder<'_, Sqlite>| -> Result<(), ApiError> {
+        qb.push(" WHERE h.ab_guid = ").push_bind(ab_guid.to_string());
+        if let Some(rustdesk_id) = rustdesk_id {
+            qb.push(" AND h.kind = 'peer' AND h.name = ")
+                .push_bind(rustdesk_id.to_string());
+        } else {
+            match query.kind.as_str() {
+                "" => {}
+                "peer" | "tag" => {
+                    qb.push(" AND h.kind = ").push_bind(query.kind.clone());
+                }
+                other => return Err(ApiError::BadRequest(format!("Unknown kind '{}'", other))),
+            }
+        }
+        Ok(())
+    };
+
+    let mut tx = state.db.begin().await?;
+
+    let mut qb = QueryBuilder::new(
+        "SELECT h.*, u.username FROM ab_history h LEFT JOIN users u ON u.id = h.user_id",
+    );
+    push_filters(&mut qb)?;
+    qb.push(" ORDER BY h.id DESC LIMIT ")
+        .push_bind(query.pageSize)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let entries = qb.build_query_as::<HistoryEntry>().fetch_all(&mut *tx).await?;
+
+    let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM ab_history h");
+    push_filters(&mut qb)?;
+    let total: i64 = qb.build_query_scalar().fetch_one(&mut *tx).await?;
+
+    tx.commit().await?;
+
+    let items: Vec<HistoryPayload> = entries.into_iter().map(HistoryPayload::from).collect();
+    Ok(Json(json!({ "data": items, "total": total })))
+}
+
+/// GET /api/ab/history/{guid} — every recorded peer and tag change in a book.
+async fn get_book_history(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(guid): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
+    history_page(&state, &guid, None, &query).await
+}
+
+/// GET /api/ab/history/{guid}/peer/{id} — changes to one peer, by RustDesk ID.
+async fn get_peer_history(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path((guid, rustdesk_id)): Path<(String, String)>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
+    history_page(&state, &guid, Some(&rustdesk_id), &query).await
+}
+
+/// POST /api/ab/history/{guid}/revert/{history_id} — put a peer back into the
+/// state recorded by a history entry. Reverting to a deletion moves the peer to
+/// the trash; reverting a trashed peer to an earlier state restores it. The
+/// revert itself is recorded as a new change.
+async fn revert_peer(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    headers: HeaderMap,
+    Path((guid, history_id)): Path<(String, i64)>,
+) -> Result<Json<Value>, ApiError> {
+    let guid = resolve_writable_ab(&state.db, claims.user_id, &guid).await?;
+
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+
+    let entry = sqlx::query_as::<_, HistoryEntry>(
+        "SELECT h.*, u.username FROM ab_history h LEFT JOIN users u ON u.id = h.user_id
+         WHERE h.id = ? AND h.ab_guid = ?",
+    )
+    .bind(history_id)
+    .bind(&guid)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("History entry not found".to_string()))?;
+
+    if entry.kind != "peer" {
+        return Err(ApiError::BadRequest("Only peer changes can be reverted".to_string()));
+    }
+
+    let peer = sqlx::query_as::<_, Peer>("SELECT * FROM peers WHERE id = ? AND ab_guid = ?")
+        .bind(entry.row_id)
+        .bind(&guid)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("Peer '{}' has been permanently deleted", entry.name))
+        })?;
+
+    let quota = PeerQuota::load(&mut tx, &state.config, &guid).await?;
+    let mut changes = BookChanges::new(&guid, claims.user_id, "revert");
+    let target = entry
+        .new_value
+        .as_deref()
+        .and_then(|v| serde_json::from_str::<Value>(v).ok());
+
+    match target {
+        None => {
+            if peer.deleted_at.is_none() {
+                sqlx::query("UPDATE peers SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?")
+                    .bind(peer.id)
+                    .execute(&mut *tx)
+                    .await?;
+                changes.delete_peer(&mut tx, &peer).await?;
+            }
+        }
+        Some(target) => {
+            let field = |name: &str, current: &str| -> String {
+                target
+                    .get(name)
+                    .and_then(Value::as_str)
+                    .unwrap_or(current)
+                    .to_string()
+            };
+            // Entries recorded before a field was tracked leave it as it is
+            let extra = match target.get("extra") {
+                Some(Value::Object(extra)) => Value::Object(extra.clone()).to_string(),
+                _ => peer.extra.clone(),
+            };
+            let custom_fields = match target.get("custom_fields") {
+                Some(Value::Object(values)) => Value::Object(values.clone()).to_string(),
+                _ => peer.custom_fields.clone(),
+            };
+            sqlx::query(
+                "UPDATE peers SET username = ?, hostname = ?, platform = ?, alias = ?, note = ?,
+                     force_always_relay = ?, rdp_port = ?, rdp_username = ?, login_name = ?,
+                     device_group_name = ?, same_server = ?, extra = ?, custom_fields = ?,
+                     deleted_at = NULL, updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ?",
+            )
+            .bind(field("username", &peer.username))
+            .bind(field("hostname", &peer.hostname))
+            .bind(field("platform", &peer.platform))
+            .bind(field("alias", &peer.alias))
+            .bind(field("note", &peer.note))
+            .bind(
+                target
+                    .get("forceAlwaysRelay")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(peer.force_always_relay),
+            )
+            .bind(field("rdpPort", &peer.rdp_port))
+            .bind(field("rdpUsername", &peer.rdp_username))
+            .bind(field("loginName", &peer.login_name))
+            .bind(field("device_group_name", &peer.device_group_name))
+            .bind(match target.get("same_server") {
+                Some(value) => value.as_bool(),
+                None => peer.same_server,
+            })
+            .bind(extra)
+            .bind(custom_fields)
+            .bind(peer.id)
+            .execute(&mut *tx)
+            .await?;
+
+            if let Some(tags) = target.get("tags").and_then(Value::as_array) {
+                let tags: Vec<String> = tags
+                    .iter()
+                    .filter_map(|t| t.as_str().map(str::to_string))
+                    .collect();
+                set_peer_tags(&mut tx, &guid, peer.id, &tags).await?;
+            }
+
+            changes.touch_peer(&mut tx, peer.id).await?;
+        }
+    }
+
+    quota.check(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({})))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+
+    use crate::test_support::TestApp;
+
+    async fn peer_history(app: &TestApp) -> Vec<Value> {
+        let (_, body) = app.call("GET", "/api/ab/history/personal/peer/100", None).await;
+        body["data"].as_array().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn reverting_restores_recorded_peer_states() {
+        let app = TestApp::new().await;
+        app.call("POST", "/api/ab/tag/add/personal", Some(json!({ "name": "web" }))).await;
+        let peer = json!({ "id": "100", "alias": "a", "note": "n", "tags": ["web"] });
+        app.call("POST", "/api/ab/peer/add/personal", Some(peer)).await;
+        let update = json!({ "id": "100", "alias": "b", "tags": [] });
+        app.call("PUT", "/api/ab/peer/update/personal", Some(update)).await;
+
+        let history = peer_history(&app).await;
+        let actions: Vec<&str> = history.iter().map(|h| h["action"].as_str().unwrap()).collect();
+        assert_eq!(actions, ["update", "create"]);
+        assert_eq!(history[0]["changed"], json!(["alias", "tags"]));
+        let created = history[1]["id"].as_i64().unwrap();
+
+        let uri = format!("/api/ab/history/personal/revert/{}", created);
+        let (status, _) = app.call("POST", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(body["data"][0]["alias"], "a");
+        assert_eq!(body["data"][0]["note"], "n");
+        assert_eq!(body["data"][0]["tags"], json!(["web"]));
+        assert_eq!(peer_history(&app).await[0]["source"], "revert");
+
+        // Reverting to the deletion trashes the peer, and back again restores it
+        app.call("DELETE", "/api/ab/peer/personal", Some(json!({ "id": "100" }))).await;
+        let deleted = peer_history(&app).await[0]["id"].as_i64().unwrap();
+        app.call("POST", &uri, None).await;
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(peer_history(&app).await[0]["action"], "restore");
+
+        let uri = format!("/api/ab/history/personal/revert/{}", deleted);
+        app.call("POST", &uri, None).await;
+        let (_, body) = app.call("GET", "/api/ab/trash/personal", None).await;
+        assert_eq!(body["total"], 1);
+
+        let (status, _) = app.call("POST", "/api/ab/history/personal/revert/999", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Read-only shares can see the history but not revert it
+        let reader = app.add_user(2, "reader").await;
+        app.share(2, 1).await;
+        let response = app.send_as(&reader, "GET", "/api/ab/history/personal", &[], None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.send_as(&reader, "POST", &uri, &[], None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
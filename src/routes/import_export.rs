@@ -0,0 +1,799 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Map, Value};
+use sqlx::{Acquire, SqliteConnection};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::address_book::*;
+use crate::models::custom_field::{CreateCustomFieldRequest, CustomField, CustomFieldPayload};
+use crate::models::peer::*;
+use crate::models::tag::Tag;
+use crate::routes::changes::BookChanges;
+use crate::routes::history::peer_snapshot;
+use crate::routes::custom_fields::{book_fields, check_definition, check_field_name};
+use crate::routes::peers::{
+    require_full_control, resolve_ab_guid, set_peer_tags, tags_for_peers, update_peer_fields,
+    upsert_peer, PeerQuota,
+};
+use crate::state::AppState;
+
+/// Columns of a CSV export, followed by one `field.<name>` column per custom field.
+const CSV_COLUMNS: [&str; 7] = ["id", "alias", "hostname", "username", "platform", "note", "tags"];
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/ab/export/{guid}", get(export_book))
+        .route("/api/ab/import/{guid}", post(import_book))
+}
+
+fn check_format(format: &str) -> Result<bool, ApiError> {
+    match format {
+        "" | "json" => Ok(false),
+        "csv" => Ok(true),
+        other => Err(ApiError::BadRequest(format!("Unknown format '{}'", other))),
+    }
+}
+
+/// Quote a CSV field when it needs it.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Split CSV text into records. Quoted fields may hold commas, doubled
+/// quotes and line breaks; blank lines are skipped.
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    records.retain(|r| !(r.len() == 1 && r[0].is_empty()));
+    Ok(records)
+}
+
+/// GET /api/ab/export/{guid}?format=json|csv — the book's peers with their
+/// tags and notes. JSON also carries tag colours and custom field definitions.
+async fn export_book(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(guid): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
+    let csv = check_format(&query.format)?;
+
+    let mut tx = state.db.begin().await?;
+
+    let name: String = sqlx::query_scalar("SELECT name FROM address_books WHERE guid = ?")
+        .bind(&guid)
+        .fetch_one(&mut *tx)
+        .await?;
+    let peers = sqlx::query_as::<_, Peer>(
+        "SELECT * FROM peers WHERE ab_guid = ? AND deleted_at IS NULL ORDER BY rustdesk_id",
+    )
+    .bind(&guid)
+    .fetch_all(&mut *tx)
+    .await?;
+    let tags = sqlx::query_as::<_, Tag>("SELECT * FROM tags WHERE ab_guid = ? ORDER BY name")
+        .bind(&guid)
+        .fetch_all(&mut *tx)
+        .await?;
+    let fields = book_fields(&mut tx, &guid).await?;
+
+    let peer_ids: Vec<i64> = peers.iter().map(|p| p.id).collect();
+    let mut peer_tags = tags_for_peers(&mut tx, &peer_ids).await?;
+
+    tx.commit().await?;
+
+    let file_name: String = guid
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+
+    if !csv {
+        let export = AbExport {
+            name,
+            tags: tags
+                .into_iter()
+                .map(|t| ExportedTag { name: t.name, color: t.color })
+                .collect(),
+            fields: fields.iter().map(CustomFieldPayload::from).collect(),
+            peers: peers
+                .iter()
+                .map(|peer| PeerPayload::new(peer, peer_tags.remove(&peer.id).unwrap_or_default()))
+                .collect(),
+        };
+        let disposition = format!("attachment; filename=\"{}.json\"", file_name);
+        return Ok(([(header::CONTENT_DISPOSITION, disposition)], Json(export)).into_response());
+    }
+
+    let mut header_row: Vec<String> = CSV_COLUMNS.iter().map(|c| c.to_string()).collect();
+    header_row.extend(fields.iter().map(|f| format!("field.{}", f.name)));
+
+    let mut out = header_row.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(",");
+    out.push_str("\r\n");
+    for peer in &peers {
+        let values = peer.custom_field_values();
+        let mut row = vec![
+            peer.rustdesk_id.clone(),
+            peer.alias.clone(),
+            peer.hostname.clone(),
+            peer.username.clone(),
+            peer.platform.clone(),
+            peer.note.clone(),
+            peer_tags.remove(&peer.id).unwrap_or_default().join(";"),
+        ];
+        row.extend(fields.iter().map(|f| match values.get(&f.name) {
+            Some(Value::String(s)) => s.clone(),
+            Some(other) => other.to_string(),
+            None => String::new(),
+        }));
+        out.push_str(&row.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+        out.push_str("\r\n");
+    }
+
+    let disposition = format!("attachment; filename=\"{}.csv\"", file_name);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        out,
+    )
+        .into_response())
+}
+
+/// The contents of an imported file. Peers that could not be read carry the
+/// reason instead.
+#[derive(Default)]
+struct ImportData {
+    tags: Vec<(String, Option<i64>)>,
+    fields: Vec<CreateCustomFieldRequest>,
+    peers: Vec<Result<Value, String>>,
+}
+
+/// Read a JSON import: this server's export, the legacy `/api/ab` data (also
+/// still wrapped in `{"data": "..."}`), or RustDesk Pro's export, which is a
+/// list of peers either bare or under `data`.
+fn parse_json(body: &str) -> Result<ImportData, String> {
+    let mut value: Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+    if let Some(Value::String(inner)) = value.get("data") {
+        value = serde_json::from_str(inner).map_err(|e| e.to_string())?;
+    }
+
+    let mut data = ImportData::default();
+    let mut book = match value {
+        Value::Array(peers) => {
+            data.peers = peers.into_iter().map(Ok).collect();
+            return Ok(data);
+        }
+        Value::Object(book) => book,
+        _ => return Err("Expected an address book object or a list of peers".to_string()),
+    };
+
+    match book.remove("peers").or_else(|| book.remove("data")) {
+        Some(Value::Array(peers)) => data.peers = peers.into_iter().map(Ok).collect(),
+        Some(_) => return Err("'peers' must be a list".to_string()),
+        None => {}
+    }
+
+    let colors: HashMap<String, i64> = match book.remove("tag_colors") {
+        Some(Value::String(colors)) => serde_json::from_str(&colors).unwrap_or_default(),
+        Some(colors) => serde_json::from_value(colors).unwrap_or_default(),
+        None => HashMap::new(),
+    };
+    if let Some(Value::Array(tags)) = book.remove("tags") {
+        for tag in tags {
+            match tag {
+                Value::String(name) => {
+                    let color = colors.get(&name).copied();
+                    data.tags.push((name, color));
+                }
+                Value::Object(tag) => {
+                    let Some(name) = tag.get("name").and_then(Value::as_str) else {
+                        return Err("Tags need a name".to_string());
+                    };
+                    let color = tag.get("color").and_then(Value::as_i64);
+                    data.tags.push((name.to_string(), color));
+                }
+                _ => return Err("Tags must be names or objects".to_string()),
+            }
+        }
+    }
+
+    if let Some(fields) = book.remove("fields") {
+        data.fields = serde_json::from_value(fields).map_err(|e| format!("fields: {}", e))?;
+    }
+
+    Ok(data)
+}
+
+/// Read a CSV import with a header row naming its columns: those of the
+/// export, in any order; `id` is required. Tags are separated by `;`.
+fn parse_csv_import(body: &str, fields: &[CustomField]) -> Result<ImportData, String> {
+    let mut records = parse_csv(body)?.into_iter();
+    let Some(header_row) = records.next() else {
+        return Ok(ImportData::default());
+    };
+
+    let columns: Vec<String> = header_row.iter().map(|c| c.trim().to_string()).collect();
+    if !columns.iter().any(|c| c == "id") {
+        return Err("The CSV header needs an 'id' column".to_string());
+    }
+    for column in &columns {
+        if let Some(name) = column.strip_prefix("field.") {
+            if !fields.iter().any(|f| f.name == name) {
+                return Err(format!("Unknown custom field '{}'", name));
+            }
+        } else if !CSV_COLUMNS.contains(&column.as_str()) {
+            return Err(format!("Unknown column '{}'", column));
+        }
+    }
+    let has_fields = columns.iter().any(|c| c.starts_with("field."));
+
+    let peers = records
+        .map(|record| {
+            if record.len() > columns.len() {
+                return Err("Row has more columns than the header".to_string());
+            }
+            let mut peer = Map::new();
+            let mut values = Map::new();
+            for (column, cell) in columns.iter().zip(record) {
+                if let Some(name) = column.strip_prefix("field.") {
+                    // An empty cell clears the field
+                    if cell.is_empty() {
+                        values.insert(name.to_string(), Value::Null);
+                        continue;
+                    }
+                    let is_number = fields
+                        .iter()
+                        .any(|f| f.name == name && f.field_type == "number");
+                    let value = if is_number {
+                        let number = cell.parse::<i64>().map(Value::from).or_else(|_| {
+                            cell.parse::<f64>()
+                                .ok()
+                                .and_then(serde_json::Number::from_f64)
+                                .map(Value::Number)
+                                .ok_or(())
+                        });
+                        number.map_err(|_| format!("Field '{}' must be a number", name))?
+                    } else {
+                        Value::String(cell)
+                    };
+                    values.insert(name.to_string(), value);
+                } else if column == "tags" {
+                    let tags: Vec<&str> =
+                        cell.split(';').map(str::trim).filter(|t| !t.is_empty()).collect();
+                    peer.insert("tags".to_string(), json!(tags));
+                } else {
+                    peer.insert(column.clone(), Value::String(cell));
+                }
+            }
+            if has_fields {
+                peer.insert("custom_fields".to_string(), Value::Object(values));
+            }
+            Ok(Value::Object(peer))
+        })
+        .collect();
+
+    Ok(ImportData {
+        peers,
+        ..ImportData::default()
+    })
+}
+
+/// Read one imported peer. The fields it gives are returned too, so merging
+/// into an existing peer can change just those.
+fn peer_request(value: Value) -> Result<(AddPeerRequest, Map<String, Value>), String> {
+    let Value::Object(mut peer) = value else {
+        return Err("Expected a peer object".to_string());
+    };
+    // Versions belong to the book the peer was exported from
+    peer.remove("version");
+    let req = serde_json::from_value(Value::Object(peer.clone())).map_err(|e| e.to_string())?;
+    Ok((req, peer))
+}
+
+/// Message of an error caused by the imported data; internal errors abort the import.
+fn row_error(err: ApiError) -> Result<String, ApiError> {
+    match err {
+        ApiError::BadRequest(msg)
+        | ApiError::Forbidden(msg)
+        | ApiError::NotFound(msg)
+        | ApiError::Conflict(msg, _)
+        | ApiError::PreconditionFailed(msg) => Ok(msg),
+        other => Err(other),
+    }
+}
+
+/// Everything about a peer that an import can change.
+async fn peer_state(
+    conn: &mut SqliteConnection,
+    peer_id: i64,
+) -> Result<(Value, String, String), ApiError> {
+    let snapshot = peer_snapshot(conn, peer_id).await?;
+    let (hash, password): (String, String) =
+        sqlx::query_as("SELECT hash, password FROM peers WHERE id = ?")
+            .bind(peer_id)
+            .fetch_one(conn)
+            .await?;
+    Ok((snapshot, hash, password))
+}
+
+/// POST /api/ab/import/{guid}?format=json|csv&mode=merge|replace&dry_run=
+///
+/// The body is the file. Peers are matched by RustDesk ID and missing tags
+/// and custom fields are created. `merge` changes only the fields (or CSV
+/// columns) the file gives on existing peers; `replace` makes each peer match
+/// the file, moves peers that are not in the file to the trash and deletes
+/// tags that neither the file lists nor its peers carry. The whole import
+/// runs in one transaction and is rolled back on a dry run or when any row
+/// has an error, so the report always shows what the import would do.
+async fn import_book(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(guid): Path<String>,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> Result<Json<Value>, ApiError> {
+    require_full_control(&state.db, claims.user_id, &guid).await?;
+    let csv = check_format(&query.format)?;
+    let replace = match query.mode.as_str() {
+        "" | "merge" => false,
+        "replace" => true,
+        other => return Err(ApiError::BadRequest(format!("Unknown import mode '{}'", other))),
+    };
+
+    let mut report = ImportReport::default();
+    let file_error = |error: String| ImportError { row: None, id: None, error };
+
+    let mut tx = state.db.begin().await?;
+    let quota = PeerQuota::load(&mut tx, &state.config, &guid).await?;
+    let mut changes = BookChanges::new(&guid, claims.user_id, "import");
+
+    let parsed = if csv {
+        let fields = book_fields(&mut tx, &guid).await?;
+        parse_csv_import(&body, &fields)
+    } else {
+        parse_json(&body)
+    };
+    let data = match parsed {
+        Ok(data) => data,
+        Err(error) => {
+            report.errors.push(file_error(error));
+            return Ok(Json(json!(report)));
+        }
+    };
+
+    // Custom field definitions come first so peer values can be checked
+    for field in &data.fields {
+        let name = field.name.trim();
+        let checked = check_field_name(name)
+            .and_then(|_| check_definition(&field.field_type, &field.options));
+        if let Err(err) = checked {
+            report.errors.push(file_error(format!("Field '{}': {}", name, row_error(err)?)));
+            continue;
+        }
+        let options =
+            serde_json::to_string(&field.options).map_err(|e| ApiError::Internal(e.to_string()))?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO ab_custom_fields (ab_guid, name, field_type, options) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&guid)
+        .bind(name)
+        .bind(&field.field_type)
+        .bind(options)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let mut requests = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, peer) in data.peers.into_iter().enumerate() {
+        let row = Some(index + 1);
+        let (req, fields) = match peer.and_then(peer_request) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                report.errors.push(ImportError { row, id: None, error });
+                continue;
+            }
+        };
+        let id = Some(req.id.clone());
+        if req.id.trim().is_empty() {
+            let error = "Peer ID is required".to_string();
+            report.errors.push(ImportError { row, id: None, error });
+        } else if !seen.insert(req.id.clone()) {
+            let error = format!("Peer '{}' appears more than once", req.id);
+            report.errors.push(ImportError { row, id, error });
+        } else {
+            requests.push((row, req, fields));
+        }
+    }
+
+    // Tags listed by the file, then any its peers carry
+    let mut wanted: BTreeMap<String, Option<i64>> = BTreeMap::new();
+    for (name, color) in data.tags {
+        if !name.is_empty() {
+            wanted.insert(name, color);
+        }
+    }
+    for (_, req, _) in &requests {
+        for name in &req.tags {
+            wanted.entry(name.clone()).or_insert(None);
+        }
+    }
+
+    let existing_tags: HashMap<String, Tag> =
+        sqlx::query_as::<_, Tag>("SELECT * FROM tags WHERE ab_guid = ?")
+            .bind(&guid)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|t| (t.name.clone(), t))
+            .collect();
+    for (name, color) in &wanted {
+        match existing_tags.get(name) {
+            Some(tag) => {
+                if let Some(color) = color.filter(|c| *c != tag.color) {
+                    sqlx::query("UPDATE tags SET color = ? WHERE id = ?")
+                        .bind(color)
+                        .bind(tag.id)
+                        .execute(&mut *tx)
+                        .await?;
+                    changes.touch_tag(&mut tx, tag.id).await?;
+                }
+            }
+            None => {
+                let tag_id = sqlx::query("INSERT INTO tags (ab_guid, name, color) VALUES (?, ?, ?)")
+                    .bind(&guid)
+                    .bind(name)
+                    .bind(color.unwrap_or(4278190080))
+                    .execute(&mut *tx)
+                    .await?
+                    .last_insert_rowid();
+                changes.touch_tag(&mut tx, tag_id).await?;
+                report.tags_created += 1;
+            }
+        }
+    }
+
+    for (row, req, fields) in requests {
+        let id = req.id.clone();
+        let clear_tags = replace && req.tags.is_empty();
+
+        let existing = sqlx::query_as::<_, Peer>(
+            "SELECT * FROM peers WHERE ab_guid = ? AND rustdesk_id = ? AND deleted_at IS NULL",
+        )
+        .bind(&guid)
+        .bind(&id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let before = match &existing {
+            Some(peer) => Some(peer_state(&mut tx, peer.id).await?),
+            None => None,
+        };
+
+        // A savepoint per row keeps a failed row from leaving half its changes.
+        // Merging into an existing peer changes only the fields the file gives;
+        // anything else replaces the whole peer.
+        let mut row_tx = tx.begin().await?;
+        let applied = match existing.filter(|_| !replace) {
+            Some(peer) => match serde_json::from_value::<UpdatePeerRequest>(Value::Object(fields)) {
+                Ok(update) => update_peer_fields(&mut row_tx, &state, &guid, &peer, update)
+                    .await
+                    .map(|_| (peer.id, false)),
+                Err(e) => Err(ApiError::BadRequest(e.to_string())),
+            },
+            None => upsert_peer(&mut row_tx, &state, &guid, req).await,
+        };
+        let (peer_id, created) = match applied {
+            Ok(applied) => applied,
+            Err(err) => {
+                row_tx.rollback().await?;
+                let error = row_error(err)?;
+                report.errors.push(ImportError { row, id: Some(id), error });
+                continue;
+            }
+        };
+        if clear_tags {
+            set_peer_tags(&mut row_tx, &guid, peer_id, &[]).await?;
+        }
+        row_tx.commit().await?;
+
+        if created {
+            report.created += 1;
+        } else if before == Some(peer_state(&mut tx, peer_id).await?) {
+            report.unchanged += 1;
+            continue;
+        } else {
+            report.updated += 1;
+        }
+        changes.touch_peer(&mut tx, peer_id).await?;
+    }
+
+    if replace {
+        let peers = sqlx::query_as::<_, Peer>(
+            "SELECT * FROM peers WHERE ab_guid = ? AND deleted_at IS NULL",
+        )
+        .bind(&guid)
+        .fetch_all(&mut *tx)
+        .await?;
+        for peer in peers.iter().filter(|p| !seen.contains(&p.rustdesk_id)) {
+            sqlx::query("UPDATE peers SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?")
+                .bind(peer.id)
+                .execute(&mut *tx)
+                .await?;
+            changes.delete_peer(&mut tx, peer).await?;
+            report.deleted += 1;
+        }
+
+        for tag in existing_tags.values().filter(|t| !wanted.contains_key(&t.name)) {
+            changes.untag_peers(&mut tx, tag.id).await?;
+            sqlx::query("DELETE FROM tags WHERE id = ?")
+                .bind(tag.id)
+                .execute(&mut *tx)
+                .await?;
+            changes.delete_tag(&mut tx, tag).await?;
+            report.tags_deleted += 1;
+        }
+    }
+
+    if let Err(err) = quota.check(&mut tx).await {
+        report.errors.push(file_error(row_error(err)?));
+    }
+
+    report.errors.sort_by_key(|e| e.row);
+    report.applied = !query.dry_run && report.errors.is_empty();
+    if report.applied {
+        tx.commit().await?;
+    } else {
+        tx.rollback().await?;
+    }
+
+    Ok(Json(json!(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::test_support::TestApp;
+
+    fn field(name: &str, field_type: &str) -> CustomField {
+        CustomField {
+            id: 0,
+            ab_guid: "personal".to_string(),
+            name: name.to_string(),
+            field_type: field_type.to_string(),
+            options: "[]".to_string(),
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn csv_handles_quotes_line_breaks_and_blank_lines() {
+        let records = parse_csv("\u{feff}a,\"b, \"\"c\"\"\",\"d\r\ne\"\r\n\r\nf,,\n").unwrap();
+        assert_eq!(
+            records,
+            vec![
+                vec!["a".to_string(), "b, \"c\"".to_string(), "d\r\ne".to_string()],
+                vec!["f".to_string(), String::new(), String::new()],
+            ]
+        );
+        assert!(parse_csv("a,\"b").is_err());
+    }
+
+    #[test]
+    fn csv_fields_round_trip() {
+        let values = ["plain", "a, b", "say \"hi\"", "two\nlines"];
+        let line = values.iter().map(|v| csv_field(v)).collect::<Vec<_>>().join(",");
+        assert_eq!(parse_csv(&line).unwrap(), vec![values.map(String::from).to_vec()]);
+    }
+
+    #[test]
+    fn csv_import_reads_tags_and_custom_fields() {
+        let fields = [field("asset", "number"), field("site", "string")];
+        let data = parse_csv_import(
+            "id,alias,tags,field.asset,field.site\n1,a, web ; db ;,42,HQ\n2,,,,\n",
+            &fields,
+        )
+        .unwrap();
+
+        let peers: Vec<Value> = data.peers.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            peers[0],
+            json!({
+                "id": "1",
+                "alias": "a",
+                "tags": ["web", "db"],
+                "custom_fields": { "asset": 42, "site": "HQ" },
+            })
+        );
+        assert_eq!(peers[1]["tags"], json!([]));
+        assert_eq!(peers[1]["custom_fields"], json!({ "asset": null, "site": null }));
+    }
+
+    #[test]
+    fn csv_import_reports_bad_rows_and_headers() {
+        let fields = [field("asset", "number")];
+        let data = parse_csv_import("id,field.asset\n1,1.5\n2,lots\n3,4,5\n", &fields).unwrap();
+        assert_eq!(data.peers[0].as_ref().unwrap()["custom_fields"]["asset"], json!(1.5));
+        assert_eq!(
+            data.peers[1].as_ref().unwrap_err(),
+            "Field 'asset' must be a number"
+        );
+        assert_eq!(
+            data.peers[2].as_ref().unwrap_err(),
+            "Row has more columns than the header"
+        );
+
+        assert!(parse_csv_import("alias\nx\n", &fields).is_err());
+        assert!(parse_csv_import("id,colour\n1,red\n", &fields).is_err());
+        assert!(parse_csv_import("id,field.site\n1,HQ\n", &fields).is_err());
+    }
+
+    #[test]
+    fn json_import_accepts_export_legacy_and_bare_list() {
+        let data = parse_json(
+            r#"{"name":"x","tags":[{"name":"web","color":5}],
+                "fields":[{"name":"site","type":"string"}],"peers":[{"id":"1"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(data.tags, vec![("web".to_string(), Some(5))]);
+        assert_eq!(data.fields.len(), 1);
+        assert_eq!(data.peers.len(), 1);
+
+        let legacy = json!({
+            "data": json!({
+                "tags": ["web", "db"],
+                "peers": [{ "id": "1", "tags": ["web"] }],
+                "tag_colors": json!({ "web": 7 }).to_string(),
+            })
+            .to_string()
+        });
+        let data = parse_json(&legacy.to_string()).unwrap();
+        assert_eq!(
+            data.tags,
+            vec![("web".to_string(), Some(7)), ("db".to_string(), None)]
+        );
+        assert_eq!(data.peers.len(), 1);
+
+        let data = parse_json(r#"{"data":[{"id":"1"},{"id":"2"}],"total":2}"#).unwrap();
+        assert_eq!(data.peers.len(), 2);
+        let data = parse_json(r#"[{"id":"1"}]"#).unwrap();
+        assert_eq!(data.peers.len(), 1);
+
+        assert!(parse_json("42").is_err());
+        assert!(parse_json(r#"{"peers":{}}"#).is_err());
+    }
+
+    async fn import(app: &TestApp, query: &str, body: &str) -> Value {
+        let uri = format!("/api/ab/import/personal?{}", query);
+        let response = app.send("POST", &uri, &[], Some(body.to_string())).await;
+        let (status, report) = crate::test_support::read_json(response).await;
+        assert_eq!(status, StatusCode::OK);
+        report
+    }
+
+    #[tokio::test]
+    async fn merge_import_keeps_fields_missing_from_the_file() {
+        let app = TestApp::new().await;
+        app.call(
+            "POST",
+            "/api/ab/peer/add/personal",
+            Some(json!({
+                "id": "1",
+                "hash": "h",
+                "alias": "old",
+                "note": "keep me",
+                "rdpPort": "3390",
+                "forceAlwaysRelay": true,
+                "custom": "x",
+            })),
+        )
+        .await;
+
+        let report = import(&app, "format=csv", "id,alias\n1,new\n").await;
+        assert_eq!(report["updated"], 1);
+        assert_eq!(report["applied"], true);
+
+        let peer = sqlx::query_as::<_, Peer>("SELECT * FROM peers WHERE rustdesk_id = '1'")
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap();
+        assert_eq!(peer.alias, "new");
+        assert_eq!(peer.note, "keep me");
+        assert_eq!(peer.hash, "h");
+        assert_eq!(peer.rdp_port, "3390");
+        assert!(peer.force_always_relay);
+        assert_eq!(peer.extra_fields()["custom"], "x");
+
+        // Importing what was exported changes nothing
+        let (_, export) = app.call("GET", "/api/ab/export/personal", None).await;
+        let report = import(&app, "", &export.to_string()).await;
+        assert_eq!(report["unchanged"], 1);
+        assert_eq!(report["updated"], 0);
+    }
+
+    #[tokio::test]
+    async fn dry_run_and_failed_imports_change_nothing() {
+        let app = TestApp::new().await;
+        app.call("POST", "/api/ab/peer/add/personal", Some(json!({ "id": "1" }))).await;
+        let revision = || async {
+            sqlx::query_scalar::<_, i64>("SELECT revision FROM address_books")
+                .fetch_one(&app.state.db)
+                .await
+                .unwrap()
+        };
+        let before = revision().await;
+
+        let file = r#"[{"id":"2","tags":["new"]},{"id":"3"}]"#;
+        let report = import(&app, "mode=replace&dry_run=true", file).await;
+        assert_eq!(report["applied"], false);
+        assert_eq!(report["created"], 2);
+        assert_eq!(report["deleted"], 1);
+        assert_eq!(report["tags_created"], 1);
+        assert_eq!(report["errors"], json!([]));
+
+        let report = import(&app, "", r#"[{"id":"2"},{"id":"2"},7]"#).await;
+        assert_eq!(report["applied"], false);
+        assert_eq!(
+            report["errors"],
+            json!([
+                { "row": 2, "id": "2", "error": "Peer '2' appears more than once" },
+                { "row": 3, "error": "Expected a peer object" },
+            ])
+        );
+
+        let (_, peers) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(peers["total"], 1);
+        let (_, tags) = app.call("GET", "/api/ab/tags/personal", None).await;
+        assert_eq!(tags["total"], 0);
+        assert_eq!(revision().await, before);
+    }
+}
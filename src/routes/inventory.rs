@@ -0,0 +1,131 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::device::*;
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/inventory/os", get(count_by_os))
+        .route("/api/inventory/versions", get(count_by_version))
+        .route("/api/inventory/outdated", get(outdated_devices))
+}
+
+fn require_admin(claims: &crate::auth::jwt::Claims) -> Result<(), ApiError> {
+    if !claims.is_admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OutdatedQuery {
+    /// Minimum acceptable client version; defaults to the newest version seen.
+    #[serde(default)]
+    pub version: String,
+}
+
+/// Numeric components of a RustDesk version string ("1.3.2" -> [1, 3, 2]),
+/// with trailing zeros dropped so "1.3" and "1.3.0" compare equal.
+fn version_key(version: &str) -> Vec<u64> {
+    let mut key: Vec<u64> = version
+        .split(['.', '-'])
+        .map(|part| {
+            part.chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect::<String>()
+                .parse()
+                .unwrap_or(0)
+        })
+        .collect();
+    while key.last() == Some(&0) {
+        key.pop();
+    }
+    key
+}
+
+/// GET /api/inventory/os — device count per reported OS.
+async fn count_by_os(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let rows = sqlx::query_as::<_, InventoryCount>(
+        "SELECT os AS value, COUNT(*) AS count FROM devices GROUP BY os ORDER BY count DESC, os",
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let total = rows.len();
+    Ok(Json(json!({ "data": rows, "total": total })))
+}
+
+/// GET /api/inventory/versions — device count per RustDesk client version, newest first.
+async fn count_by_version(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let mut rows = sqlx::query_as::<_, InventoryCount>(
+        "SELECT version AS value, COUNT(*) AS count FROM devices GROUP BY version",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    rows.sort_by_key(|r| std::cmp::Reverse(version_key(&r.value)));
+
+    let total = rows.len();
+    Ok(Json(json!({ "data": rows, "total": total })))
+}
+
+/// GET /api/inventory/outdated — devices running a client older than `version`.
+/// Devices that never reported a version are not included.
+async fn outdated_devices(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<OutdatedQuery>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let devices = sqlx::query_as::<_, Device>(
+        "SELECT * FROM devices WHERE version != '' ORDER BY rustdesk_id",
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let target = if query.version.is_empty() {
+        devices
+            .iter()
+            .map(|d| version_key(&d.version))
+            .max()
+            .unwrap_or_default()
+    } else {
+        version_key(&query.version)
+    };
+
+    let items: Vec<Value> = devices
+        .into_iter()
+        .filter(|d| version_key(&d.version) < target)
+        .map(|d| {
+            json!({
+                "id": d.id,
+                "rustdesk_id": d.rustdesk_id,
+                "hostname": d.hostname,
+                "os": d.os,
+                "version": d.version,
+                "last_online": d.last_online,
+            })
+        })
+        .collect();
+
+    let total = items.len();
+    Ok(Json(json!({ "data": items, "total": total })))
+}
@@ -1,10 +1,23 @@
 pub mod ab;
 pub mod auth;
+pub mod batch;
+pub mod changes;
+pub mod custom_fields;
+pub mod device_groups;
+pub mod devices;
+pub mod duplicates;
 pub mod frontend;
 pub mod groups;
+pub mod hbbs;
+pub mod history;
+pub mod import_export;
+pub mod inventory;
 pub mod peers;
+pub mod search;
+pub mod strategies;
 pub mod system;
 pub mod tags;
+pub mod trash;
 pub mod users;
 
 use axum::Router;
@@ -15,8 +28,21 @@ pub fn api_router() -> Router<AppState> {
         .merge(auth::routes())
         .merge(ab::routes())
         .merge(peers::routes())
+        .merge(batch::routes())
         .merge(tags::routes())
+        .merge(changes::routes())
+        .merge(search::routes())
+        .merge(trash::routes())
+        .merge(history::routes())
+        .merge(duplicates::routes())
+        .merge(import_export::routes())
+        .merge(hbbs::routes())
+        .merge(custom_fields::routes())
         .merge(system::routes())
         .merge(users::routes())
         .merge(groups::routes())
+        .merge(devices::routes())
+        .merge(device_groups::routes())
+        .merge(strategies::routes())
+        .merge(inventory::routes())
 }
@@ -1,19 +1,34 @@
 use axum::{
     extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
     routing::{delete, get, post, put},
     Json, Router,
 };
 use serde::Deserialize;
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection};
+use std::collections::HashMap;
 
 use crate::auth::middleware::AuthUser;
 use crate::error::ApiError;
+use crate::models::device::Device;
 use crate::models::peer::*;
+use crate::routes::custom_fields::{
+    book_fields, carry_custom_fields, field_path, validate_custom_fields,
+};
+use crate::routes::changes::{
+    book_revision, check_if_match, not_modified, peers_etag, BookChanges,
+};
 use crate::state::AppState;
 
 pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/api/ab/peers", get(get_peers))
+        .route("/api/ab/peers/backfill", post(backfill_peers))
+        .route("/api/ab/peers/move", post(move_peers))
+        .route("/api/ab/peers/copy", post(copy_peers))
+        .route("/api/ab/peers/rotate-passwords", post(rotate_passwords))
         .route("/api/ab/peer/add/{guid}", post(add_peer))
         .route("/api/ab/peer/update/{guid}", put(update_peer))
         .route("/api/ab/peer/{guid}", delete(delete_peers))
@@ -28,12 +43,414 @@ pub struct PeersQuery {
     pub pageSize: i64,
     #[serde(default)]
     pub ab: String,
+    /// Merge live data from `devices` into the returned peers.
+    #[serde(default)]
+    pub with_device: bool,
+    /// Free text matched against id, alias, hostname, username and note.
+    #[serde(default)]
+    pub q: String,
+    /// Comma-separated tag names, combined according to `tag_mode`.
+    #[serde(default)]
+    pub tags: String,
+    /// `any` (default), `all` or `none`; `none` without `tags` selects untagged peers.
+    #[serde(default)]
+    pub tag_mode: String,
+    #[serde(default)]
+    pub platform: String,
+    /// Only peers whose device is (or isn't) currently online.
+    #[serde(default)]
+    pub online: Option<bool>,
+    /// `id` (default), `alias`, `hostname`, `created` or `updated`.
+    #[serde(default)]
+    pub sort: String,
+    /// `asc` (default) or `desc`.
+    #[serde(default)]
+    pub order: String,
 }
 
 fn default_page_size() -> i64 {
     100
 }
 
+/// Custom field conditions of a peer listing, from `field.<name>=value` query
+/// parameters: JSON path into `custom_fields` and the value to compare with.
+async fn custom_field_filters(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+    params: &[(String, String)],
+) -> Result<Vec<(String, Value)>, ApiError> {
+    let requested: Vec<(&str, &str)> = params
+        .iter()
+        .filter_map(|(key, value)| key.strip_prefix("field.").map(|name| (name, value.as_str())))
+        .collect();
+    if requested.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let fields = book_fields(conn, ab_guid).await?;
+    let mut filters = Vec::new();
+    for (name, value) in requested {
+        let Some(field) = fields.iter().find(|f| f.name == name) else {
+            return Err(ApiError::BadRequest(format!("Unknown custom field '{}'", name)));
+        };
+        let value = if field.field_type == "number" {
+            let number: f64 = value.parse().map_err(|_| {
+                ApiError::BadRequest(format!("Field '{}' must be a number", name))
+            })?;
+            json!(number)
+        } else {
+            json!(value)
+        };
+        filters.push((field_path(name), value));
+    }
+    Ok(filters)
+}
+
+/// Append the `WHERE` clause for a peer listing to `qb`. Peer columns are
+/// expected to be reachable through the alias `p`.
+fn push_peer_filters(
+    qb: &mut QueryBuilder<'_, Sqlite>,
+    ab_guid: &str,
+    query: &PeersQuery,
+    fields: &[(String, Value)],
+) -> Result<(), ApiError> {
+    qb.push(" WHERE p.deleted_at IS NULL AND p.ab_guid = ")
+        .push_bind(ab_guid.to_string());
+
+    let q = query.q.trim();
+    if !q.is_empty() {
+        let pattern = format!(
+            "%{}%",
+            q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+        );
+        qb.push(" AND (");
+        for (i, column) in ["rustdesk_id", "alias", "hostname", "username", "note"]
+            .iter()
+            .enumerate()
+        {
+            if i > 0 {
+                qb.push(" OR ");
+            }
+            qb.push(format!("p.{} LIKE ", column))
+                .push_bind(pattern.clone())
+                .push(" ESCAPE '\\'");
+        }
+        qb.push(")");
+    }
+
+    let tags: Vec<&str> = query
+        .tags
+        .split(',')
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .collect();
+    let tagged = " FROM peer_tags pt JOIN tags t ON t.id = pt.tag_id WHERE pt.peer_id = p.id";
+    match (query.tag_mode.as_str(), tags.is_empty()) {
+        ("" | "any" | "all", true) => {}
+        ("none", true) => {
+            qb.push(" AND NOT EXISTS (SELECT 1 FROM peer_tags pt WHERE pt.peer_id = p.id)");
+        }
+        (mode @ ("" | "any" | "none"), false) => {
+            qb.push(if mode == "none" {
+                " AND NOT EXISTS (SELECT 1"
+            } else {
+                " AND EXISTS (SELECT 1"
+            });
+            qb.push(tagged).push(" AND t.name IN (");
+            let mut names = qb.separated(", ");
+            for tag in &tags {
+                names.push_bind(tag.to_string());
+            }
+            qb.push("))");
+        }
+        ("all", false) => {
+            let unique: std::collections::HashSet<&str> = tags.iter().copied().collect();
+            qb.push(" AND (SELECT COUNT(DISTINCT t.name)")
+                .push(tagged)
+                .push(" AND t.name IN (");
+            let mut names = qb.separated(", ");
+            for tag in &unique {
+                names.push_bind(tag.to_string());
+            }
+            qb.push(")) = ").push_bind(unique.len() as i64);
+        }
+        (mode, _) => {
+            return Err(ApiError::BadRequest(format!("Unknown tag_mode '{}'", mode)));
+        }
+    }
+
+    if !query.platform.is_empty() {
+        qb.push(" AND p.platform = ")
+            .push_bind(query.platform.clone())
+            .push(" COLLATE NOCASE");
+    }
+
+    for (path, value) in fields {
+        qb.push(" AND json_extract(p.custom_fields, ")
+            .push_bind(path.clone())
+            .push(") = ");
+        match value {
+            Value::Number(n) => qb.push_bind(n.as_f64().unwrap_or_default()),
+            other => qb.push_bind(other.as_str().unwrap_or_default().to_string()),
+        };
+    }
+
+    if let Some(online) = query.online {
+        qb.push(if online { " AND EXISTS" } else { " AND NOT EXISTS" }).push(
+            " (SELECT 1 FROM devices d
+               WHERE d.rustdesk_id = p.rustdesk_id AND d.online AND d.status != 'rejected')",
+        );
+    }
+
+    Ok(())
+}
+
+/// `ORDER BY` clause for a peer listing; the RustDesk ID breaks ties so pages are stable.
+fn peer_order(query: &PeersQuery) -> Result<String, ApiError> {
+    let column = match query.sort.as_str() {
+        "" | "id" => "p.rustdesk_id",
+        "alias" => "p.alias",
+        "hostname" => "p.hostname",
+        "created" => "p.created_at",
+        "updated" => "p.updated_at",
+        other => return Err(ApiError::BadRequest(format!("Unknown sort '{}'", other))),
+    };
+    let direction = match query.order.as_str() {
+        "" | "asc" => "ASC",
+        "desc" => "DESC",
+        other => return Err(ApiError::BadRequest(format!("Unknown order '{}'", other))),
+    };
+    Ok(format!(" ORDER BY {} {}, p.rustdesk_id {}", column, direction, direction))
+}
+
+fn require_admin(claims: &crate::auth::jwt::Claims) -> Result<(), ApiError> {
+    if !claims.is_admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+    Ok(())
+}
+
+/// Devices matching the peers of an address book, keyed by RustDesk ID.
+/// Rejected devices are left out.
+pub async fn devices_for_book(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+) -> Result<HashMap<String, Device>, ApiError> {
+    let devices = sqlx::query_as::<_, Device>(
+        "SELECT * FROM devices
+         WHERE status != 'rejected'
+         AND rustdesk_id IN (SELECT rustdesk_id FROM peers WHERE ab_guid = ? AND deleted_at IS NULL)",
+    )
+    .bind(ab_guid)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(devices
+        .into_iter()
+        .map(|d| (d.rustdesk_id.clone(), d))
+        .collect())
+}
+
+/// Tag names of the given peers keyed by peer row id, loaded with a single
+/// query instead of one per peer.
+pub async fn tags_for_peers(
+    conn: &mut SqliteConnection,
+    peer_ids: &[i64],
+) -> Result<HashMap<i64, Vec<String>>, ApiError> {
+    let ids = serde_json::to_string(peer_ids).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let rows: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT pt.peer_id, t.name FROM peer_tags pt
+         JOIN tags t ON t.id = pt.tag_id
+         WHERE pt.peer_id IN (SELECT value FROM json_each(?))
+         ORDER BY t.name",
+    )
+    .bind(ids)
+    .fetch_all(conn)
+    .await?;
+
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for (peer_id, name) in rows {
+        tags.entry(peer_id).or_default().push(name);
+    }
+    Ok(tags)
+}
+
+/// Load a peer and check it against the version the client last saw.
+/// A mismatch is a `Conflict` carrying the peer as it is now; an expected
+/// version of 0 means the peer must not exist yet. Trashed peers count as
+/// not existing.
+pub async fn check_peer_version(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+    rustdesk_id: &str,
+    expected: Option<i64>,
+) -> Result<Option<Peer>, ApiError> {
+    let peer = sqlx::query_as::<_, Peer>(
+        "SELECT * FROM peers WHERE ab_guid = ? AND rustdesk_id = ? AND deleted_at IS NULL",
+    )
+    .bind(ab_guid)
+    .bind(rustdesk_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(expected) = expected else {
+        return Ok(peer);
+    };
+    if expected == peer.as_ref().map(|p| p.version).unwrap_or(0) {
+        return Ok(peer);
+    }
+
+    let Some(peer) = peer else {
+        return Err(ApiError::Conflict(
+            format!("Peer '{}' has been deleted", rustdesk_id),
+            None,
+        ));
+    };
+    let mut tags = tags_for_peers(conn, &[peer.id]).await?;
+    let current = PeerPayload::new(&peer, tags.remove(&peer.id).unwrap_or_default());
+    Err(ApiError::Conflict(
+        format!("Peer '{}' has been modified", rustdesk_id),
+        Some(json!(current)),
+    ))
+}
+
+/// Replace a peer's tags with the named tags of its book; unknown names are ignored.
+pub async fn set_peer_tags(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+    peer_id: i64,
+    tags: &[String],
+) -> Result<(), ApiError> {
+    sqlx::query("DELETE FROM peer_tags WHERE peer_id = ?")
+        .bind(peer_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for tag_name in tags {
+        let tag_id: Option<i64> = sqlx::query_scalar(
+            "SELECT id FROM tags WHERE ab_guid = ? AND name = ?",
+        )
+        .bind(ab_guid)
+        .bind(tag_name)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        if let Some(tag_id) = tag_id {
+            sqlx::query("INSERT OR IGNORE INTO peer_tags (peer_id, tag_id) VALUES (?, ?)")
+                .bind(peer_id)
+                .bind(tag_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The user's effective rule on an address book: 3 (full control) for the
+/// owner, otherwise the highest rule granted to the user or one of their
+/// groups. `None` when the book doesn't exist or isn't shared with them.
+pub async fn ab_rule(
+    db: &sqlx::SqlitePool,
+    user_id: i64,
+    ab_guid: &str,
+) -> Result<Option<i32>, ApiError> {
+    let rule: Option<Option<i32>> = sqlx::query_scalar(
+        "SELECT CASE WHEN ab.owner_id = ? THEN 3 ELSE (
+             SELECT MAX(s.rule) FROM ab_shares s
+             WHERE s.ab_guid = ab.guid AND (s.user_id = ? OR s.group_id IN (SELECT group_id FROM user_groups WHERE user_id = ?))
+         ) END
+         FROM address_books ab WHERE ab.guid = ?",
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(ab_guid)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(rule.flatten())
+}
+
+/// Fail unless the user has full control (rule 3) over the address book.
+pub async fn require_full_control(
+    db: &sqlx::SqlitePool,
+    user_id: i64,
+    ab_guid: &str,
+) -> Result<(), ApiError> {
+    match ab_rule(db, user_id, ab_guid).await? {
+        Some(3) => Ok(()),
+        Some(_) => Err(ApiError::Forbidden(
+            "Full control of the address book is required".to_string(),
+        )),
+        None => Err(ApiError::Forbidden("Access denied to this address book".to_string())),
+    }
+}
+
+/// Guards an address book's peer limit across a write. The live peer count is
+/// taken when loaded; `check` fails if the write grew the book past its limit.
+/// Books already over a lowered limit can still be edited, just not grown.
+pub struct PeerQuota {
+    ab_guid: String,
+    limit: i64,
+    before: i64,
+}
+
+impl PeerQuota {
+    pub async fn load(
+        conn: &mut SqliteConnection,
+        config: &crate::config::Config,
+        ab_guid: &str,
+    ) -> Result<Self, ApiError> {
+        let limit = peer_limit(conn, config, ab_guid).await?;
+        let before = live_peer_count(conn, ab_guid).await?;
+        Ok(PeerQuota {
+            ab_guid: ab_guid.to_string(),
+            limit,
+            before,
+        })
+    }
+
+    pub async fn check(&self, conn: &mut SqliteConnection) -> Result<(), ApiError> {
+        if self.limit <= 0 {
+            return Ok(());
+        }
+        let after = live_peer_count(conn, &self.ab_guid).await?;
+        if after > self.limit && after > self.before {
+            return Err(ApiError::BadRequest(format!(
+                "Address book is limited to {} peers",
+                self.limit
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Effective peer limit of a book: its own `max_peers`, else the global one.
+/// 0 means unlimited.
+pub async fn peer_limit(
+    conn: &mut SqliteConnection,
+    config: &crate::config::Config,
+    ab_guid: &str,
+) -> Result<i64, ApiError> {
+    let own: Option<Option<i64>> =
+        sqlx::query_scalar("SELECT max_peers FROM address_books WHERE guid = ?")
+            .bind(ab_guid)
+            .fetch_optional(conn)
+            .await?;
+    Ok(own.flatten().unwrap_or(config.max_peers_per_ab as i64))
+}
+
+async fn live_peer_count(conn: &mut SqliteConnection, ab_guid: &str) -> Result<i64, ApiError> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM peers WHERE ab_guid = ? AND deleted_at IS NULL",
+    )
+    .bind(ab_guid)
+    .fetch_one(conn)
+    .await?;
+    Ok(count)
+}
+
 /// Verify the user has access to the given address book guid.
 /// Returns the guid of the personal AB if `ab` is empty.
 pub async fn resolve_ab_guid(
@@ -74,11 +491,41 @@ pub async fn resolve_ab_guid(
     Ok(ab_guid.to_string())
 }
 
+/// Resolve an address book the user needs to write to: read/write (2) or
+/// full control (3).
+pub async fn resolve_writable_ab(
+    db: &sqlx::SqlitePool,
+    user_id: i64,
+    ab_guid: &str,
+) -> Result<String, ApiError> {
+    let guid = resolve_ab_guid(db, user_id, ab_guid).await?;
+    match ab_rule(db, user_id, &guid).await? {
+        Some(rule) if rule >= 2 => Ok(guid),
+        _ => Err(ApiError::Forbidden(
+            "Write access to the address book is required".to_string(),
+        )),
+    }
+}
+
+/// Decrypted saved password of a peer, for users with full control only.
+fn saved_password(state: &AppState, full_control: bool, peer: &Peer) -> Option<PeerPassword> {
+    if !full_control || peer.password.is_empty() {
+        return None;
+    }
+    let password = state.peer_keys.decrypt(&peer.password, &peer.ab_guid, &peer.rustdesk_id);
+    if password.is_none() {
+        tracing::warn!("Could not decrypt a saved peer password; is its key still configured?");
+    }
+    password.map(PeerPassword)
+}
+
 async fn get_peers(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
+    headers: HeaderMap,
     Query(query): Query<PeersQuery>,
-) -> Result<Json<PeersResponse>, ApiError> {
+    Query(params): Query<Vec<(String, String)>>,
+) -> Result<Response, ApiError> {
     let guid = resolve_ab_guid(&state.db, claims.user_id, &query.ab).await?;
 
     let offset = if query.current > 0 {
@@ -87,60 +534,139 @@ async fn get_peers(
         0
     };
 
-    let peers = sqlx::query_as::<_, Peer>(
-        "SELECT * FROM peers WHERE ab_guid = ? ORDER BY rustdesk_id LIMIT ? OFFSET ?",
-    )
-    .bind(&guid)
-    .bind(query.pageSize)
-    .bind(offset)
-    .fetch_all(&state.db)
-    .await?;
+    let order = peer_order(&query)?;
+    let full_control = ab_rule(&state.db, claims.user_id, &guid).await? == Some(3);
 
-    let total: i64 =
-        sqlx::query_scalar("SELECT COUNT(*) FROM peers WHERE ab_guid = ?")
-            .bind(&guid)
-            .fetch_one(&state.db)
-            .await?;
+    // Page, count and tags are read in one transaction so they agree
+    let mut tx = state.db.begin().await?;
+    let field_filters = custom_field_filters(&mut tx, &guid, &params).await?;
+
+    // Live device data isn't covered by the book's revision, so no ETag then
+    let etag = if query.with_device || query.online.is_some() {
+        None
+    } else {
+        let revision = book_revision(&mut tx, &guid).await?;
+        Some(peers_etag(&guid, revision, full_control))
+    };
+    if let Some(response) = etag.as_deref().and_then(|etag| not_modified(&headers, etag)) {
+        return Ok(response);
+    }
+
+    let mut qb = QueryBuilder::new("SELECT p.* FROM peers p");
+    push_peer_filters(&mut qb, &guid, &query, &field_filters)?;
+    qb.push(order)
+        .push(" LIMIT ")
+        .push_bind(query.pageSize)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let peers = qb.build_query_as::<Peer>().fetch_all(&mut *tx).await?;
+
+    let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM peers p");
+    push_peer_filters(&mut qb, &guid, &query, &field_filters)?;
+    let total: i64 = qb.build_query_scalar().fetch_one(&mut *tx).await?;
+
+    let peer_ids: Vec<i64> = peers.iter().map(|p| p.id).collect();
+    let mut tags = tags_for_peers(&mut tx, &peer_ids).await?;
+
+    let devices = if query.with_device {
+        devices_for_book(&mut tx, &guid).await?
+    } else {
+        HashMap::new()
+    };
+
+    tx.commit().await?;
 
     let mut payloads = Vec::new();
     for peer in &peers {
-        let tags: Vec<String> = sqlx::query_scalar(
-            "SELECT t.name FROM tags t JOIN peer_tags pt ON t.id = pt.tag_id WHERE pt.peer_id = ?",
-        )
-        .bind(peer.id)
-        .fetch_all(&state.db)
-        .await
-        .unwrap_or_default();
+        let tags = tags.remove(&peer.id).unwrap_or_default();
 
-        payloads.push(PeerPayload {
-            id: peer.rustdesk_id.clone(),
-            hash: peer.hash.clone(),
-            username: peer.username.clone(),
-            hostname: peer.hostname.clone(),
-            platform: peer.platform.clone(),
-            alias: peer.alias.clone(),
-            tags,
-            note: peer.note.clone(),
-        });
+        let mut payload = PeerPayload::new(peer, tags);
+        payload.password = saved_password(&state, full_control, peer);
+        if query.with_device {
+            let device = devices.get(&peer.rustdesk_id);
+            if let Some(device) = device {
+                if !device.hostname.is_empty() {
+                    payload.hostname = device.hostname.clone();
+                }
+                if !device.platform.is_empty() {
+                    payload.platform = device.platform.clone();
+                }
+                payload.last_online = Some(device.last_online.clone());
+            }
+            payload.online = Some(device.map(|d| d.online).unwrap_or(false));
+        }
+        payloads.push(payload);
     }
 
-    Ok(Json(PeersResponse {
+    let body = Json(PeersResponse {
         data: payloads,
         total,
-    }))
+    });
+    Ok(match etag {
+        Some(etag) => ([(header::ETAG, etag)], body).into_response(),
+        None => body.into_response(),
+    })
 }
 
 async fn add_peer(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
+    headers: HeaderMap,
     Path(guid): Path<String>,
     Json(req): Json<AddPeerRequest>,
 ) -> Result<Json<Value>, ApiError> {
     let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
 
-    sqlx::query(
-        "INSERT INTO peers (ab_guid, rustdesk_id, hash, username, hostname, platform, alias, note)
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+    let quota = PeerQuota::load(&mut tx, &state.config, &guid).await?;
+
+    let (peer_id, _) = upsert_peer(&mut tx, &state, &guid, req).await?;
+
+    BookChanges::new(&guid, claims.user_id, "peer_add")
+        .touch_peer(&mut tx, peer_id)
+        .await?;
+    quota.check(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({})))
+}
+
+/// Insert a peer or replace the one with the same RustDesk ID, checking the
+/// expected version and custom field values. Returns the peer's row id and
+/// whether it was created (a peer restored from the trash counts as created).
+/// The caller records the change.
+pub async fn upsert_peer(
+    tx: &mut SqliteConnection,
+    state: &AppState,
+    guid: &str,
+    req: AddPeerRequest,
+) -> Result<(i64, bool), ApiError> {
+    let existing = check_peer_version(tx, guid, &req.id, req.version).await?;
+
+    let password = match &req.password {
+        Some(password) => Some(state.peer_keys.encrypt(&password.0, guid, &req.id)?),
+        None => None,
+    };
+
+    let extra = Value::Object(storable_extra(req.extra)).to_string();
+    let custom_fields = match req.custom_fields {
+        Some(values) => {
+            validate_custom_fields(tx, guid, &values).await?;
+            let values: Map<String, Value> =
+                values.into_iter().filter(|(_, v)| !v.is_null()).collect();
+            Some(Value::Object(values).to_string())
+        }
+        None => None,
+    };
+
+    // Get the actual peer id back (might differ if it was an update).
+    // Re-adding a peer that is in the trash restores it.
+    let actual_peer_id: i64 = sqlx::query_scalar(
+        "INSERT INTO peers (ab_guid, rustdesk_id, hash, username, hostname, platform, alias, note,
+             password, force_always_relay, rdp_port, rdp_username, login_name, device_group_name,
+             same_server, extra, custom_fields)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, COALESCE(?, ''), ?, ?, ?, ?, ?, ?, ?, COALESCE(?, '{}'))
          ON CONFLICT(ab_guid, rustdesk_id) DO UPDATE SET
              hash = excluded.hash,
              username = excluded.username,
@@ -148,9 +674,20 @@ async fn add_peer(
              platform = excluded.platform,
              alias = excluded.alias,
              note = excluded.note,
-             updated_at = CURRENT_TIMESTAMP",
+             password = COALESCE(?, password),
+             force_always_relay = excluded.force_always_relay,
+             rdp_port = excluded.rdp_port,
+             rdp_username = excluded.rdp_username,
+             login_name = excluded.login_name,
+             device_group_name = excluded.device_group_name,
+             same_server = excluded.same_server,
+             extra = excluded.extra,
+             custom_fields = COALESCE(?, custom_fields),
+             updated_at = CURRENT_TIMESTAMP,
+             deleted_at = NULL
+         RETURNING id",
     )
-    .bind(&guid)
+    .bind(guid)
     .bind(&req.id)
     .bind(&req.hash)
     .bind(&req.username)
@@ -158,64 +695,126 @@ async fn add_peer(
     .bind(&req.platform)
     .bind(&req.alias)
     .bind(&req.note)
-    .execute(&state.db)
+    .bind(&password)
+    .bind(req.force_always_relay)
+    .bind(&req.rdp_port)
+    .bind(&req.rdp_username)
+    .bind(&req.login_name)
+    .bind(&req.device_group_name)
+    .bind(req.same_server)
+    .bind(&extra)
+    .bind(&custom_fields)
+    .bind(&password)
+    .bind(&custom_fields)
+    .fetch_one(&mut *tx)
     .await?;
 
     // Update tags if provided
     if !req.tags.is_empty() {
-        // Get the actual peer id (might differ if it was an update)
-        let actual_peer_id: i64 = sqlx::query_scalar(
-            "SELECT id FROM peers WHERE ab_guid = ? AND rustdesk_id = ?",
-        )
-        .bind(&guid)
-        .bind(&req.id)
-        .fetch_one(&state.db)
-        .await?;
-
-        sqlx::query("DELETE FROM peer_tags WHERE peer_id = ?")
-            .bind(actual_peer_id)
-            .execute(&state.db)
-            .await?;
-
-        for tag_name in &req.tags {
-            let tag_id: Option<i64> = sqlx::query_scalar(
-                "SELECT id FROM tags WHERE ab_guid = ? AND name = ?",
-            )
-            .bind(&guid)
-            .bind(tag_name)
-            .fetch_optional(&state.db)
-            .await?;
-
-            if let Some(tag_id) = tag_id {
-                sqlx::query("INSERT OR IGNORE INTO peer_tags (peer_id, tag_id) VALUES (?, ?)")
-                    .bind(actual_peer_id)
-                    .bind(tag_id)
-                    .execute(&state.db)
-                    .await?;
-            }
-        }
+        set_peer_tags(tx, guid, actual_peer_id, &req.tags).await?;
     }
 
-    Ok(Json(json!({})))
+    Ok((actual_peer_id, existing.is_none()))
 }
 
+/// PUT /api/ab/peer/update/{guid} — change the given fields of an existing peer.
 async fn update_peer(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
+    headers: HeaderMap,
     Path(guid): Path<String>,
-    Json(_req): Json<UpdatePeerRequest>,
+    Json(req): Json<UpdatePeerRequest>,
 ) -> Result<Json<Value>, ApiError> {
-    let _guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
+    let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
+
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+    let Some(peer) = check_peer_version(&mut tx, &guid, &req.id, req.version).await? else {
+        return Err(ApiError::NotFound(format!("Peer '{}' not found", req.id)));
+    };
+
+    update_peer_fields(&mut tx, &state, &guid, &peer, req).await?;
 
-    // The client typically uses add_peer with upsert semantics for updates.
-    // This endpoint exists for compatibility.
+    BookChanges::new(&guid, claims.user_id, "peer_update")
+        .touch_peer(&mut tx, peer.id)
+        .await?;
+    tx.commit().await?;
 
     Ok(Json(json!({})))
 }
 
+/// Change the fields given in `req` of an existing peer, keeping the others.
+/// The caller records the change.
+pub async fn update_peer_fields(
+    tx: &mut SqliteConnection,
+    state: &AppState,
+    guid: &str,
+    peer: &Peer,
+    req: UpdatePeerRequest,
+) -> Result<(), ApiError> {
+    let password = match &req.password {
+        Some(password) => state.peer_keys.encrypt(&password.0, guid, &peer.rustdesk_id)?,
+        None => peer.password.clone(),
+    };
+
+    let mut custom_fields = peer.custom_field_values();
+    if let Some(values) = req.custom_fields {
+        validate_custom_fields(tx, guid, &values).await?;
+        for (key, value) in values {
+            if value.is_null() {
+                custom_fields.remove(&key);
+            } else {
+                custom_fields.insert(key, value);
+            }
+        }
+    }
+
+    let mut extra = peer.extra_fields();
+    for (key, value) in storable_extra(req.extra) {
+        if value.is_null() {
+            extra.remove(&key);
+        } else {
+            extra.insert(key, value);
+        }
+    }
+
+    sqlx::query(
+        "UPDATE peers SET hash = ?, username = ?, hostname = ?, platform = ?, alias = ?, note = ?,
+             password = ?, force_always_relay = ?, rdp_port = ?, rdp_username = ?, login_name = ?,
+             device_group_name = ?, same_server = ?, extra = ?, custom_fields = ?,
+             updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?",
+    )
+    .bind(req.hash.as_ref().unwrap_or(&peer.hash))
+    .bind(req.username.as_ref().unwrap_or(&peer.username))
+    .bind(req.hostname.as_ref().unwrap_or(&peer.hostname))
+    .bind(req.platform.as_ref().unwrap_or(&peer.platform))
+    .bind(req.alias.as_ref().unwrap_or(&peer.alias))
+    .bind(req.note.as_ref().unwrap_or(&peer.note))
+    .bind(&password)
+    .bind(req.force_always_relay.unwrap_or(peer.force_always_relay))
+    .bind(req.rdp_port.as_ref().unwrap_or(&peer.rdp_port))
+    .bind(req.rdp_username.as_ref().unwrap_or(&peer.rdp_username))
+    .bind(req.login_name.as_ref().unwrap_or(&peer.login_name))
+    .bind(req.device_group_name.as_ref().unwrap_or(&peer.device_group_name))
+    .bind(req.same_server.or(peer.same_server))
+    .bind(Value::Object(extra).to_string())
+    .bind(Value::Object(custom_fields).to_string())
+    .bind(peer.id)
+    .execute(&mut *tx)
+    .await?;
+
+    if let Some(tags) = &req.tags {
+        set_peer_tags(tx, guid, peer.id, tags).await?;
+    }
+
+    Ok(())
+}
+
 async fn delete_peers(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
+    headers: HeaderMap,
     Path(guid): Path<String>,
     Json(req): Json<DeletePeersRequest>,
 ) -> Result<Json<Value>, ApiError> {
@@ -226,22 +825,670 @@ async fn delete_peers(
         ids_to_delete.push(id);
     }
 
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+    let mut changes = BookChanges::new(&guid, claims.user_id, "peer_delete");
+
     for rustdesk_id in &ids_to_delete {
-        // Delete peer_tags first
+        let expected = req.versions.get(rustdesk_id).copied();
+        let Some(peer) = check_peer_version(&mut tx, &guid, rustdesk_id, expected).await? else {
+            continue;
+        };
+
+        // Move to the trash; tags are kept so a restore brings them back
+        sqlx::query("UPDATE peers SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(peer.id)
+            .execute(&mut *tx)
+            .await?;
+        changes.delete_peer(&mut tx, &peer).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(json!({})))
+}
+
+/// POST /api/ab/peers/backfill — refresh stale peer hostname/platform from the
+/// matching device's latest sysinfo. Limited to one book when `ab` is given.
+async fn backfill_peers(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<Value>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let ab = req.get("ab").and_then(|v| v.as_str()).unwrap_or_default();
+
+    let mut tx = state.db.begin().await?;
+
+    let stale: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT p.id, p.ab_guid FROM peers p
+         WHERE (? = '' OR p.ab_guid = ?) AND p.deleted_at IS NULL AND EXISTS (
+             SELECT 1 FROM devices d
+             WHERE d.rustdesk_id = p.rustdesk_id AND d.status != 'rejected'
+             AND ((d.hostname != '' AND d.hostname != p.hostname)
+                  OR (d.platform != '' AND d.platform != p.platform))
+         )
+         ORDER BY p.ab_guid",
+    )
+    .bind(ab)
+    .bind(ab)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut changes: HashMap<String, BookChanges> = HashMap::new();
+    for (peer_id, ab_guid) in &stale {
         sqlx::query(
-            "DELETE FROM peer_tags WHERE peer_id IN (SELECT id FROM peers WHERE ab_guid = ? AND rustdesk_id = ?)",
+            "UPDATE peers SET
+                 hostname = COALESCE((SELECT NULLIF(d.hostname, '') FROM devices d WHERE d.rustdesk_id = peers.rustdesk_id), hostname),
+                 platform = COALESCE((SELECT NULLIF(d.platform, '') FROM devices d WHERE d.rustdesk_id = peers.rustdesk_id), platform),
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?",
         )
-        .bind(&guid)
+        .bind(peer_id)
+        .execute(&mut *tx)
+        .await?;
+
+        changes
+            .entry(ab_guid.clone())
+            .or_insert_with(|| BookChanges::new(ab_guid, claims.user_id, "backfill"))
+            .touch_peer(&mut tx, *peer_id)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(json!({ "updated": stale.len() })))
+}
+
+/// Make sure every tag carried by the given peers exists in `ab_guid`, creating
+/// missing ones with the colour they have in the peer's own book. Returns the
+/// union of the tag names.
+pub async fn carry_tags(
+    conn: &mut SqliteConnection,
+    changes: &mut BookChanges,
+    ab_guid: &str,
+    peer_ids: &[i64],
+) -> Result<Vec<String>, ApiError> {
+    let ids = serde_json::to_string(peer_ids).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let tags: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT t.name, MIN(t.color) FROM peer_tags pt JOIN tags t ON t.id = pt.tag_id
+         WHERE pt.peer_id IN (SELECT value FROM json_each(?))
+         GROUP BY t.name ORDER BY t.name",
+    )
+    .bind(ids)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for (name, color) in &tags {
+        let result = sqlx::query("INSERT OR IGNORE INTO tags (ab_guid, name, color) VALUES (?, ?, ?)")
+            .bind(ab_guid)
+            .bind(name)
+            .bind(color)
+            .execute(&mut *conn)
+            .await?;
+        if result.rows_affected() > 0 {
+            changes.touch_tag(conn, result.last_insert_rowid()).await?;
+        }
+    }
+
+    Ok(tags.into_iter().map(|(name, _)| name).collect())
+}
+
+/// Copy the requested peers of `req.from` into `req.to`, moving them to the
+/// trash of the source book when `remove_source` is set. Tags are matched by
+/// name; ones missing from the destination are created with their source
+/// colour. Peers already live in the destination are reported as conflicts
+/// and left alone in both books.
+async fn transfer_peers(
+    state: &AppState,
+    user_id: i64,
+    req: &TransferPeersRequest,
+    remove_source: bool,
+) -> Result<(Vec<String>, Vec<String>, Vec<String>), ApiError> {
+    let from = resolve_writable_ab(&state.db, user_id, &req.from).await?;
+    let to = resolve_writable_ab(&state.db, user_id, &req.to).await?;
+    if from == to {
+        return Err(ApiError::BadRequest(
+            "Source and destination address books are the same".to_string(),
+        ));
+    }
+
+    let source = if remove_source { "peer_move" } else { "peer_copy" };
+    let mut from_changes = BookChanges::new(&from, user_id, source);
+    let mut to_changes = BookChanges::new(&to, user_id, source);
+
+    let mut done = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut not_found = Vec::new();
+
+    let mut tx = state.db.begin().await?;
+    let quota = PeerQuota::load(&mut tx, &state.config, &to).await?;
+
+    for rustdesk_id in &req.ids {
+        let peer = sqlx::query_as::<_, Peer>(
+            "SELECT * FROM peers WHERE ab_guid = ? AND rustdesk_id = ? AND deleted_at IS NULL",
+        )
+        .bind(&from)
         .bind(rustdesk_id)
-        .execute(&state.db)
+        .fetch_optional(&mut *tx)
         .await?;
+        let Some(peer) = peer else {
+            not_found.push(rustdesk_id.clone());
+            continue;
+        };
+
+        let exists: bool = sqlx::query_scalar(
+            "SELECT COUNT(*) > 0 FROM peers WHERE ab_guid = ? AND rustdesk_id = ? AND deleted_at IS NULL",
+        )
+        .bind(&to)
+        .bind(rustdesk_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if exists {
+            conflicts.push(rustdesk_id.clone());
+            continue;
+        }
 
-        sqlx::query("DELETE FROM peers WHERE ab_guid = ? AND rustdesk_id = ?")
-            .bind(&guid)
-            .bind(rustdesk_id)
-            .execute(&state.db)
+        // Values for fields the destination doesn't define are left behind
+        let custom_fields = carry_custom_fields(&mut tx, &to, peer.custom_field_values()).await?;
+        let password = state.peer_keys.reseal(
+            &peer.password,
+            (&from, &peer.rustdesk_id),
+            (&to, &peer.rustdesk_id),
+        )?;
+
+        // A copy of the peer sitting in the destination's trash is overwritten
+        let peer_id: i64 = sqlx::query_scalar(
+            "INSERT INTO peers (ab_guid, rustdesk_id, hash, username, hostname, platform, alias, note,
+                 password, force_always_relay, rdp_port, rdp_username, login_name, device_group_name,
+                 same_server, extra, custom_fields)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(ab_guid, rustdesk_id) DO UPDATE SET
+                 hash = excluded.hash,
+                 username = excluded.username,
+                 hostname = excluded.hostname,
+                 platform = excluded.platform,
+                 alias = excluded.alias,
+                 note = excluded.note,
+                 password = excluded.password,
+                 force_always_relay = excluded.force_always_relay,
+                 rdp_port = excluded.rdp_port,
+                 rdp_username = excluded.rdp_username,
+                 login_name = excluded.login_name,
+                 device_group_name = excluded.device_group_name,
+                 same_server = excluded.same_server,
+                 extra = excluded.extra,
+                 custom_fields = excluded.custom_fields,
+                 updated_at = CURRENT_TIMESTAMP,
+                 deleted_at = NULL
+             RETURNING id",
+        )
+        .bind(&to)
+        .bind(&peer.rustdesk_id)
+        .bind(&peer.hash)
+        .bind(&peer.username)
+        .bind(&peer.hostname)
+        .bind(&peer.platform)
+        .bind(&peer.alias)
+        .bind(&peer.note)
+        .bind(&password)
+        .bind(peer.force_always_relay)
+        .bind(&peer.rdp_port)
+        .bind(&peer.rdp_username)
+        .bind(&peer.login_name)
+        .bind(&peer.device_group_name)
+        .bind(peer.same_server)
+        .bind(&peer.extra)
+        .bind(Value::Object(custom_fields).to_string())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let names = carry_tags(&mut tx, &mut to_changes, &to, &[peer.id]).await?;
+        set_peer_tags(&mut tx, &to, peer_id, &names).await?;
+        to_changes.touch_peer(&mut tx, peer_id).await?;
+
+        if remove_source {
+            sqlx::query("UPDATE peers SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?")
+                .bind(peer.id)
+                .execute(&mut *tx)
+                .await?;
+            from_changes.delete_peer(&mut tx, &peer).await?;
+        }
+
+        done.push(rustdesk_id.clone());
+    }
+
+    quota.check(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok((done, conflicts, not_found))
+}
+
+/// POST /api/ab/peers/move — move peers, with their tags and notes, to another
+/// address book. The originals go to the source book's trash.
+async fn move_peers(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<TransferPeersRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let (moved, conflicts, not_found) = transfer_peers(&state, claims.user_id, &req, true).await?;
+    Ok(Json(json!({ "moved": moved, "conflicts": conflicts, "not_found": not_found })))
+}
+
+/// POST /api/ab/peers/copy — copy peers, with their tags and notes, to another
+/// address book.
+async fn copy_peers(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<TransferPeersRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let (copied, conflicts, not_found) = transfer_peers(&state, claims.user_id, &req, false).await?;
+    Ok(Json(json!({ "copied": copied, "conflicts": conflicts, "not_found": not_found })))
+}
+
+/// POST /api/ab/peers/rotate-passwords — re-encrypt saved peer passwords that
+/// were written with an older key, so that key can be removed from the config.
+async fn rotate_passwords(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+    if !state.peer_keys.is_enabled() {
+        return Err(ApiError::BadRequest(
+            "Storing peer passwords is not enabled on this server".to_string(),
+        ));
+    }
+
+    let mut tx = state.db.begin().await?;
+
+    let stored: Vec<(i64, String, String, String)> = sqlx::query_as(
+        "SELECT id, ab_guid, rustdesk_id, password FROM peers WHERE password != ''",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut rotated = 0;
+    let mut failed = 0;
+    for (peer_id, ab_guid, rustdesk_id, password) in &stored {
+        if state.peer_keys.is_current(password) {
+            continue;
+        }
+        let Some(plaintext) = state.peer_keys.decrypt(password, ab_guid, rustdesk_id) else {
+            failed += 1;
+            continue;
+        };
+        sqlx::query("UPDATE peers SET password = ? WHERE id = ?")
+            .bind(state.peer_keys.encrypt(&plaintext, ab_guid, rustdesk_id)?)
+            .bind(peer_id)
+            .execute(&mut *tx)
             .await?;
+        rotated += 1;
     }
 
-    Ok(Json(json!({})))
+    tx.commit().await?;
+
+    Ok(Json(json!({ "rotated": rotated, "failed": failed })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, StatusCode};
+    use serde_json::json;
+
+    use crate::auth::peer_password::PeerPasswordKeys;
+    use crate::test_support::{read_json, TestApp};
+
+    /// Seed the test book with `count` peers, each carrying two of 20 tags.
+    async fn seed_peers(app: &TestApp, count: i64) {
+        let db = &app.state.db;
+        sqlx::query(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 20)
+             INSERT INTO tags (ab_guid, name, color) SELECT 'personal', 'tag' || i, i FROM n",
+        )
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < ?)
+             INSERT INTO peers (ab_guid, rustdesk_id, hostname, alias, note)
+             SELECT 'personal', printf('%09d', i), 'host-' || i, 'alias ' || i, 'note ' || i FROM n",
+        )
+        .bind(count)
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO peer_tags (peer_id, tag_id)
+             SELECT p.id, t.id FROM peers p JOIN tags t
+             ON t.name IN ('tag' || (p.id % 20 + 1), 'tag' || ((p.id + 7) % 20 + 1))",
+        )
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    /// Statements run to answer a GET, with its JSON body.
+    async fn count_queries(app: &TestApp, uri: &str) -> (usize, serde_json::Value) {
+        let before = app.queries_run();
+        let (status, body) = app.call("GET", uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        (app.queries_run() - before, body)
+    }
+
+    /// Listing a 10k-peer book, through both the paged and the legacy endpoint,
+    /// runs the same statements as listing two peers: none of them per peer.
+    #[tokio::test]
+    async fn peer_listings_of_10k_peers_run_no_per_peer_queries() {
+        let small = TestApp::new().await;
+        seed_peers(&small, 2).await;
+        let large = TestApp::new().await;
+        seed_peers(&large, 10_000).await;
+
+        let uri = "/api/ab/peers?ab=personal&pageSize=10000";
+        let (small_queries, _) = count_queries(&small, uri).await;
+        let (large_queries, body) = count_queries(&large, uri).await;
+        assert!(small_queries > 0);
+        assert_eq!(body["total"], 10_000);
+        assert_eq!(body["data"].as_array().unwrap().len(), 10_000);
+        assert_eq!(body["data"][0]["tags"].as_array().unwrap().len(), 2);
+        assert_eq!(large_queries, small_queries);
+
+        let (small_queries, _) = count_queries(&small, "/api/ab").await;
+        let (large_queries, body) = count_queries(&large, "/api/ab").await;
+        let data: serde_json::Value =
+            serde_json::from_str(body["data"].as_str().unwrap()).unwrap();
+        assert_eq!(data["peers"].as_array().unwrap().len(), 10_000);
+        assert_eq!(large_queries, small_queries);
+    }
+
+    /// RustDesk IDs of a peer listing, in the order returned.
+    async fn listed_ids(app: &TestApp, params: &str) -> Vec<String> {
+        let uri = format!("/api/ab/peers?ab=personal&{}", params);
+        let (status, body) = app.call("GET", &uri, None).await;
+        assert_eq!(status, StatusCode::OK, "{}: {}", params, body);
+        body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|peer| peer["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn peer_listings_filter_and_sort() {
+        let app = TestApp::new().await;
+        sqlx::query(
+            "INSERT INTO tags (ab_guid, name, color) VALUES ('personal', 'work', 0), ('personal', 'home', 0)",
+        )
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+        let peers = [
+            json!({ "id": "100", "alias": "100% uptime", "platform": "Windows", "tags": ["work"] }),
+            json!({ "id": "200", "alias": "a_b", "platform": "Linux", "tags": ["work", "home"] }),
+            json!({ "id": "300", "alias": "axb", "platform": "linux", "tags": ["home"] }),
+            json!({ "id": "400", "alias": "zed", "platform": "Mac OS" }),
+        ];
+        for peer in peers {
+            let (status, _) = app.call("POST", "/api/ab/peer/add/personal", Some(peer)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        sqlx::query("INSERT INTO devices (rustdesk_id, online) VALUES ('200', TRUE), ('300', FALSE)")
+            .execute(&app.state.db)
+            .await
+            .unwrap();
+
+        // LIKE wildcards in q match literally
+        assert_eq!(listed_ids(&app, "q=%25").await, ["100"]);
+        assert_eq!(listed_ids(&app, "q=_").await, ["200"]);
+        assert_eq!(listed_ids(&app, "q=ZE").await, ["400"]);
+
+        assert_eq!(listed_ids(&app, "tags=work,home").await, ["100", "200", "300"]);
+        assert_eq!(listed_ids(&app, "tags=work,home&tag_mode=all").await, ["200"]);
+        assert_eq!(listed_ids(&app, "tags=work&tag_mode=none").await, ["300", "400"]);
+        assert_eq!(listed_ids(&app, "tag_mode=none").await, ["400"]);
+
+        assert_eq!(listed_ids(&app, "platform=LINUX").await, ["200", "300"]);
+        assert_eq!(listed_ids(&app, "online=true").await, ["200"]);
+        assert_eq!(listed_ids(&app, "online=false").await, ["100", "300", "400"]);
+
+        assert_eq!(listed_ids(&app, "sort=alias").await, ["100", "200", "300", "400"]);
+        assert_eq!(listed_ids(&app, "sort=alias&order=desc").await, ["400", "300", "200", "100"]);
+        assert_eq!(listed_ids(&app, "order=desc&pageSize=2").await, ["400", "300"]);
+
+        for params in ["sort=password", "order=up", "tag_mode=some"] {
+            let uri = format!("/api/ab/peers?ab=personal&{}", params);
+            let (status, _) = app.call("GET", &uri, None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", params);
+        }
+    }
+
+    #[tokio::test]
+    async fn peer_list_etags_tell_passwords_apart() {
+        let app = TestApp::new().await;
+        let reader = app.add_user(2, "reader").await;
+        app.share(2, 2).await;
+        let peer = json!({ "id": "111", "password": "pw" });
+        let (status, _) = app.call("POST", "/api/ab/peer/add/personal", Some(peer)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let uri = "/api/ab/peers?ab=personal";
+        let response = app.send("GET", uri, &[], None).await;
+        let owner_etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+        let (_, body) = read_json(response).await;
+        assert_eq!(body["data"][0]["password"], "pw");
+
+        // The owner's cached page must not be reused for a user without full control
+        let response = app
+            .send_as(&reader, "GET", uri, &[("If-None-Match", owner_etag.as_str())], None)
+            .await;
+        let reader_etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+        let (status, body) = read_json(response).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(reader_etag, owner_etag);
+        assert!(body["data"][0].get("password").is_none());
+
+        let response = app.send("GET", uri, &[("If-None-Match", owner_etag.as_str())], None).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // Either tag of the current revision passes If-Match; a stale one doesn't
+        let update = |alias: &str| Some(json!({ "id": "111", "alias": alias }).to_string());
+        let uri = "/api/ab/peer/update/personal";
+        let response = app
+            .send("PUT", uri, &[("If-Match", reader_etag.as_str())], update("a"))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app
+            .send("PUT", uri, &[("If-Match", owner_etag.as_str())], update("b"))
+            .await;
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+        let response = app.send("GET", "/api/ab/peers?ab=personal", &[], None).await;
+        let owner_etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+        let response = app
+            .send("PUT", uri, &[("If-Match", owner_etag.as_str())], update("c"))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(body["data"][0]["alias"], "c");
+    }
+
+    #[tokio::test]
+    async fn stale_peer_versions_are_rejected() {
+        let app = TestApp::new().await;
+        let add = json!({ "id": "111", "alias": "a", "version": 0 });
+        let (status, _) = app.call("POST", "/api/ab/peer/add/personal", Some(add.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Version 0 means "must not exist yet"
+        let (status, body) = app.call("POST", "/api/ab/peer/add/personal", Some(add)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["current"]["alias"], "a");
+
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        let version = body["data"][0]["version"].as_i64().unwrap();
+
+        let uri = "/api/ab/peer/update/personal";
+        let update = json!({ "id": "111", "alias": "b", "version": version });
+        let (status, _) = app.call("PUT", uri, Some(update)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // A second writer holding the old version gets the current peer back
+        let update = json!({ "id": "111", "alias": "c", "version": version });
+        let (status, body) = app.call("PUT", uri, Some(update.clone())).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["current"]["alias"], "b");
+        assert!(body["current"]["version"].as_i64().unwrap() > version);
+
+        let delete = json!({ "id": "111", "versions": { "111": version } });
+        let (status, _) = app.call("DELETE", "/api/ab/peer/personal", Some(delete)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let delete = json!({ "id": "111" });
+        let (status, _) = app.call("DELETE", "/api/ab/peer/personal", Some(delete)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = app.call("PUT", uri, Some(update)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.get("current").is_none());
+    }
+
+    #[tokio::test]
+    async fn peers_move_or_copy_with_their_tags() {
+        let app = TestApp::new().await;
+        app.add_book("team", 1).await;
+        let tag = json!({ "name": "work", "color": 5 });
+        app.call("POST", "/api/ab/tag/add/personal", Some(tag)).await;
+        for id in ["100", "200", "300"] {
+            let peer = json!({ "id": id, "note": format!("note {}", id), "tags": ["work"] });
+            app.call("POST", "/api/ab/peer/add/personal", Some(peer)).await;
+        }
+        let peer = json!({ "id": "300", "alias": "theirs" });
+        app.call("POST", "/api/ab/peer/add/team", Some(peer)).await;
+
+        let copy = json!({ "from": "personal", "to": "team", "ids": ["100", "300", "999"] });
+        let (status, body) = app.call("POST", "/api/ab/peers/copy", Some(copy)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "copied": ["100"], "conflicts": ["300"], "not_found": ["999"] }));
+
+        let (_, body) = app.call("GET", "/api/ab/tags/team", None).await;
+        assert_eq!(body["data"][0]["name"], "work");
+        assert_eq!(body["data"][0]["color"], 5);
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=team", None).await;
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["data"][0]["note"], "note 100");
+        assert_eq!(body["data"][0]["tags"], json!(["work"]));
+        // A conflicting peer is left alone in both books
+        assert_eq!(body["data"][1]["alias"], "theirs");
+        assert_eq!(body["data"][1]["tags"], json!([]));
+
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(body["total"], 3);
+
+        let transfer = json!({ "from": "personal", "to": "team", "ids": ["200"] });
+        let (status, body) = app.call("POST", "/api/ab/peers/move", Some(transfer)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "moved": ["200"], "conflicts": [], "not_found": [] }));
+
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(body["total"], 2);
+        let (_, body) = app.call("GET", "/api/ab/trash/personal", None).await;
+        assert_eq!(body["data"][0]["id"], "200");
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=team", None).await;
+        assert_eq!(body["data"][1]["id"], "200");
+        assert_eq!(body["data"][1]["tags"], json!(["work"]));
+
+        let same = json!({ "from": "personal", "to": "personal", "ids": ["100"] });
+        let (status, _) = app.call("POST", "/api/ab/peers/copy", Some(same)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn saved_passwords_need_full_control_and_survive_key_rotation() {
+        let mut app = TestApp::new().await;
+        let writer = app.add_user(2, "writer").await;
+        app.share(2, 2).await;
+        let peer = json!({ "id": "111", "password": "pw" });
+        app.call("POST", "/api/ab/peer/add/personal", Some(peer)).await;
+        let uri = "/api/ab/peers?ab=personal";
+
+        let (_, body) = app.call("GET", uri, None).await;
+        assert_eq!(body["data"][0]["password"], "pw");
+        let (_, body) = read_json(app.send_as(&writer, "GET", uri, &[], None).await).await;
+        assert!(body["data"][0].get("password").is_none());
+
+        // Copies are re-encrypted for their new book
+        app.add_book("team", 1).await;
+        let copy = json!({ "from": "personal", "to": "team", "ids": ["111"] });
+        app.call("POST", "/api/ab/peers/copy", Some(copy)).await;
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=team", None).await;
+        assert_eq!(body["data"][0]["password"], "pw");
+
+        let k1 = "k1:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string();
+        let k2 = "k2:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=".to_string();
+        app.state.peer_keys = PeerPasswordKeys::new(&[k2.clone(), k1]).unwrap();
+        let (_, body) = app.call("GET", uri, None).await;
+        assert_eq!(body["data"][0]["password"], "pw");
+        let (status, body) = app.call("POST", "/api/ab/peers/rotate-passwords", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "rotated": 2, "failed": 0 }));
+
+        app.state.peer_keys = PeerPasswordKeys::new(&[k2]).unwrap();
+        let (_, body) = app.call("GET", uri, None).await;
+        assert_eq!(body["data"][0]["password"], "pw");
+
+        // A key that is no longer configured leaves the password out
+        let k3 = "k3:AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=".to_string();
+        app.state.peer_keys = PeerPasswordKeys::new(&[k3]).unwrap();
+        let (_, body) = app.call("GET", uri, None).await;
+        assert!(body["data"][0].get("password").is_none());
+    }
+
+    #[tokio::test]
+    async fn peer_limits_stop_books_growing_but_not_edits() {
+        let mut app = TestApp::new().await;
+        app.state.config.max_peers_per_ab = 2;
+        let add = |id: &str| Some(json!({ "id": id }));
+        let legacy = |ids: &[&str]| {
+            let peers: Vec<_> = ids.iter().map(|id| json!({ "id": id, "alias": "x" })).collect();
+            let data = json!({ "tags": [], "peers": peers, "tag_colors": "{}" });
+            Some(json!({ "data": data.to_string() }))
+        };
+
+        let (_, body) = app.call("GET", "/api/ab/settings?ab=personal", None).await;
+        assert_eq!(body["max_peer_one_ab"], 2);
+        for id in ["100", "200"] {
+            let (status, _) = app.call("POST", "/api/ab/peer/add/personal", add(id)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let (status, body) = app.call("POST", "/api/ab/peer/add/personal", add("300")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "Address book is limited to 2 peers");
+        let (status, _) = app.call("POST", "/api/ab", legacy(&["100", "200", "300"])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // A book's own limit overrides the global one, even below its size
+        let limit = json!({ "max_peer_one_ab": 1 });
+        let (status, _) = app.call("PUT", "/api/ab/settings/personal", Some(limit)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = app.call("GET", "/api/ab/settings?ab=personal", None).await;
+        assert_eq!(body["max_peer_one_ab"], 1);
+
+        let update = json!({ "id": "100", "alias": "edited" });
+        let (status, _) = app.call("PUT", "/api/ab/peer/update/personal", Some(update)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = app.call("POST", "/api/ab", legacy(&["100", "200"])).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = app.call("POST", "/api/ab/peer/add/personal", add("300")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let delete = json!({ "id": "200" });
+        app.call("DELETE", "/api/ab/peer/personal", Some(delete)).await;
+        let restore = json!({ "ids": ["200"] });
+        let (status, _) = app.call("POST", "/api/ab/trash/personal/restore", Some(restore)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(body["total"], 1);
+    }
 }
@@ -0,0 +1,203 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use sqlx::SqliteConnection;
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::peer::*;
+use crate::routes::peers::tags_for_peers;
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/api/ab/search", get(search))
+}
+
+/// Rebuild the search index entries of the peers selected by `filter`, a
+/// condition on `peers p` with a single `?` placeholder bound to `value`.
+///
+/// The index has no triggers behind it; writes go through `BookChanges`,
+/// which calls this for every peer it touches.
+pub async fn reindex_peers(
+    conn: &mut SqliteConnection,
+    filter: &str,
+    value: i64,
+) -> Result<(), ApiError> {
+    sqlx::query(&format!(
+        "DELETE FROM peer_search WHERE rowid IN (SELECT p.id FROM peers p WHERE {})",
+        filter
+    ))
+    .bind(value)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(&format!(
+        "INSERT INTO peer_search (rowid, rustdesk_id, alias, hostname, username, note, tags, custom_fields)
+         SELECT p.id, p.rustdesk_id, p.alias, p.hostname, p.username, p.note,
+                COALESCE((SELECT group_concat(t.name, ' ') FROM peer_tags pt
+                          JOIN tags t ON t.id = pt.tag_id WHERE pt.peer_id = p.id), ''),
+                COALESCE((SELECT group_concat(f.value, ' ') FROM json_each(p.custom_fields) f), '')
+         FROM peers p WHERE p.deleted_at IS NULL AND ({})",
+        filter
+    ))
+    .bind(value)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Drop a peer from the search index.
+pub async fn unindex_peer(conn: &mut SqliteConnection, peer_id: i64) -> Result<(), ApiError> {
+    sqlx::query("DELETE FROM peer_search WHERE rowid = ?")
+        .bind(peer_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Turn free text into an FTS5 query: every word must match, as a prefix.
+/// Words are quoted so FTS5 operators in the input are taken literally.
+fn match_expression(q: &str) -> String {
+    q.split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// GET /api/ab/search?q= — search peers across every address book the caller
+/// owns or has been shared, best matches first.
+async fn search(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let expression = match_expression(&query.q);
+    if expression.is_empty() {
+        return Err(ApiError::BadRequest("Search query is required".to_string()));
+    }
+
+    let mut tx = state.db.begin().await?;
+
+    let rows: Vec<(i64, String, String, f64)> = sqlx::query_as(
+        "SELECT p.id, ab.guid, ab.name, bm25(peer_search)
+         FROM peer_search
+         JOIN peers p ON p.id = peer_search.rowid
+         JOIN address_books ab ON ab.guid = p.ab_guid
+         WHERE peer_search MATCH ? AND (
+             ab.owner_id = ?
+             OR EXISTS (SELECT 1 FROM ab_shares s WHERE s.ab_guid = ab.guid AND (s.user_id = ? OR s.group_id IN (SELECT group_id FROM user_groups WHERE user_id = ?)))
+         )
+         ORDER BY bm25(peer_search)
+         LIMIT ?",
+    )
+    .bind(&expression)
+    .bind(claims.user_id)
+    .bind(claims.user_id)
+    .bind(claims.user_id)
+    .bind(query.limit.clamp(1, 500))
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let peer_ids: Vec<i64> = rows.iter().map(|r| r.0).collect();
+    let ids = serde_json::to_string(&peer_ids).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let peers = sqlx::query_as::<_, Peer>(
+        "SELECT * FROM peers WHERE id IN (SELECT value FROM json_each(?))",
+    )
+    .bind(ids)
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut tags = tags_for_peers(&mut tx, &peer_ids).await?;
+
+    tx.commit().await?;
+
+    let mut peers: std::collections::HashMap<i64, Peer> =
+        peers.into_iter().map(|p| (p.id, p)).collect();
+    let mut hits = Vec::new();
+    for (peer_id, ab_guid, ab_name, rank) in rows {
+        let Some(peer) = peers.remove(&peer_id) else {
+            continue;
+        };
+        hits.push(PeerSearchHit {
+            ab_guid,
+            ab_name,
+            peer: PeerPayload::new(&peer, tags.remove(&peer.id).unwrap_or_default()),
+            rank,
+        });
+    }
+
+    let total = hits.len();
+    Ok(Json(json!({ "data": hits, "total": total })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    use crate::test_support::{read_json, TestApp};
+
+    /// Address books of the hits a search returns to the holder of `token`, sorted.
+    async fn hit_books(app: &TestApp, token: &str, q: &str) -> Vec<String> {
+        let uri = format!("/api/ab/search?q={}", q);
+        let (status, body) = read_json(app.send_as(token, "GET", &uri, &[], None).await).await;
+        assert_eq!(status, StatusCode::OK, "{}: {}", q, body);
+        let mut books: Vec<String> = body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| hit["ab_guid"].as_str().unwrap().to_string())
+            .collect();
+        books.sort();
+        books
+    }
+
+    #[tokio::test]
+    async fn search_covers_visible_books_only() {
+        let app = TestApp::new().await;
+        let carol = app.add_user(3, "carol").await;
+        app.add_book("carols", 3).await;
+        for guid in ["direct", "grouped"] {
+            app.add_book(guid, 1).await;
+        }
+        sqlx::query(
+            "INSERT INTO groups (id, name) VALUES (1, 'ops');
+             INSERT INTO user_groups (user_id, group_id) VALUES (3, 1);
+             INSERT INTO ab_shares (ab_guid, user_id, rule) VALUES ('direct', 3, 1);
+             INSERT INTO ab_shares (ab_guid, group_id, rule) VALUES ('grouped', 1, 1)",
+        )
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+
+        let peer = |id: &str| Some(json!({ "id": id, "alias": "build server" }).to_string());
+        let uri = "/api/ab/peer/add/carols";
+        let response = app.send_as(&carol, "POST", uri, &[], peer("100")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        for (guid, id) in [("direct", "200"), ("grouped", "300"), ("personal", "400")] {
+            let uri = format!("/api/ab/peer/add/{}", guid);
+            assert_eq!(app.send("POST", &uri, &[], peer(id)).await.status(), StatusCode::OK);
+        }
+
+        // The admin's own book isn't shared with carol
+        assert_eq!(hit_books(&app, &carol, "serv").await, ["carols", "direct", "grouped"]);
+
+        let delete = json!({ "id": "200" });
+        let (status, _) = app.call("DELETE", "/api/ab/peer/direct", Some(delete)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(hit_books(&app, &carol, "build%20server").await, ["carols", "grouped"]);
+
+        // FTS5 syntax is matched as plain words instead of failing the query
+        assert_eq!(hit_books(&app, &carol, "server%22").await, ["carols", "grouped"]);
+        assert_eq!(hit_books(&app, &carol, "build%20OR%20nothing").await, Vec::<String>::new());
+        for q in ["NEAR(build", "alias%3Abuild", "-build", "*", "%5Ebuild"] {
+            hit_books(&app, &carol, q).await;
+        }
+
+        let (status, _) = app.call("GET", "/api/ab/search?q=%20", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,474 @@
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get, put},
+    Json, Router,
+};
+use serde_json::{json, Map, Value};
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::strategy::*;
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/strategies", get(list_strategies).post(create_strategy))
+        .route(
+            "/api/strategies/{id}",
+            put(update_strategy).delete(delete_strategy),
+        )
+        .route(
+            "/api/strategies/{id}/assignments",
+            get(list_assignments).post(assign_strategy),
+        )
+        .route("/api/strategy-assignments/{id}", delete(unassign_strategy))
+}
+
+fn require_admin(claims: &crate::auth::jwt::Claims) -> Result<(), ApiError> {
+    if !claims.is_admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+    Ok(())
+}
+
+/// Resolve the config options a device should run with and their version.
+///
+/// The assigned strategy is picked by precedence (device, then user, then
+/// device group). A one-off `strategy` command queued for the device that is
+/// newer than the assignment is layered on top of it until the device
+/// acknowledges it. After that only its version still counts, and only while
+/// a strategy is assigned, so the device isn't sent the assignment again just
+/// to undo the push.
+pub async fn effective_strategy(
+    db: &sqlx::SqlitePool,
+    device_id: i64,
+) -> Result<Option<EffectiveStrategy>, ApiError> {
+    let assigned: Option<(String, i64)> = sqlx::query_as(
+        "SELECT s.options, MAX(s.modified_at, a.modified_at)
+         FROM strategy_assignments a
+         JOIN strategies s ON s.id = a.strategy_id
+         JOIN devices d ON d.id = ?
+         WHERE s.enabled AND (
+             a.device_id = d.id OR a.user_id = d.user_id OR a.device_group_id = d.device_group_id
+         )
+         ORDER BY CASE
+             WHEN a.device_id IS NOT NULL THEN 0
+             WHEN a.user_id IS NOT NULL THEN 1
+             ELSE 2
+         END
+         LIMIT 1",
+    )
+    .bind(device_id)
+    .fetch_optional(db)
+    .await?;
+
+    let pushed: Option<(String, String)> = sqlx::query_as(
+        "SELECT payload, status FROM device_commands WHERE device_id = ? AND command = 'strategy'
+         ORDER BY id DESC LIMIT 1",
+    )
+    .bind(device_id)
+    .fetch_optional(db)
+    .await?;
+
+    let mut version = 0;
+    let mut assigned_options = Map::new();
+    let has_assignment = assigned.is_some();
+    if let Some((options, assigned_version)) = assigned {
+        assigned_options = serde_json::from_str(&options).unwrap_or_default();
+        version = assigned_version;
+    }
+    let mut options = assigned_options.clone();
+    if let Some((payload, status)) = pushed {
+        let payload: Value = serde_json::from_str(&payload).unwrap_or_default();
+        let pushed_version = payload["modified_at"].as_i64().unwrap_or(0);
+        if pushed_version > version && status != "acked" {
+            if let Some(pushed_options) = payload["config_options"].as_object() {
+                options.extend(pushed_options.clone());
+            }
+            version = pushed_version;
+        } else if pushed_version > version && has_assignment {
+            version = pushed_version;
+        }
+    }
+
+    if version == 0 {
+        return Ok(None);
+    }
+    Ok(Some(EffectiveStrategy {
+        version,
+        options,
+        assigned: assigned_options,
+    }))
+}
+
+/// The strategy version a device is on, and the options to send it when it
+/// doesn't have that version yet: either because its strategy changed since
+/// the last heartbeat, or because the `modified_at` it reports is older (a
+/// lost response or a fresh client). Once no strategy applies any more, the
+/// device gets a config clearing every option it was assigned.
+///
+/// Each device counts its own versions, apart from the address books in
+/// `modified_at`, and only moves on when the version of the effective strategy
+/// (its source) or the assigned options change. A reset keeps its version on
+/// later heartbeats.
+pub async fn device_strategy(
+    db: &sqlx::SqlitePool,
+    device_id: i64,
+    reported_modified_at: i64,
+) -> Result<(i64, Option<Map<String, Value>>), ApiError> {
+    let effective = effective_strategy(db, device_id).await?;
+
+    let (sent_version, sent_source, sent_options): (i64, i64, String) = sqlx::query_as(
+        "SELECT strategy_version, strategy_source, strategy_options FROM devices WHERE id = ?",
+    )
+    .bind(device_id)
+    .fetch_one(db)
+    .await?;
+    let sent: Map<String, Value> = serde_json::from_str(&sent_options).unwrap_or_default();
+
+    let (source, options, assigned) = match effective {
+        Some(strategy) => (strategy.version, strategy.options, strategy.assigned),
+        // Nothing was assigned, or it has already been cleared
+        None if sent.values().all(|v| v.as_str() == Some("")) => {
+            (sent_source, sent.clone(), sent.clone())
+        }
+        // The client drops options set to an empty value
+        None => {
+            let cleared: Map<String, Value> =
+                sent.keys().map(|key| (key.clone(), json!(""))).collect();
+            (0, cleared.clone(), cleared)
+        }
+    };
+
+    let changed = source != sent_source || assigned != sent;
+    let version = if changed {
+        let version = chrono::Utc::now().timestamp().max(sent_version + 1);
+        sqlx::query(
+            "UPDATE devices SET strategy_version = ?, strategy_source = ?, strategy_options = ?
+             WHERE id = ?",
+        )
+        .bind(version)
+        .bind(source)
+        .bind(Value::Object(assigned).to_string())
+        .bind(device_id)
+        .execute(db)
+        .await?;
+        version
+    } else {
+        sent_version
+    };
+    if version == 0 {
+        return Ok((0, None));
+    }
+
+    let send = changed || reported_modified_at < version;
+    Ok((version, send.then_some(options)))
+}
+
+async fn list_strategies(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let strategies = sqlx::query_as::<_, Strategy>("SELECT * FROM strategies ORDER BY id")
+        .fetch_all(&state.db)
+        .await?;
+
+    let items: Vec<StrategyListItem> = strategies
+        .into_iter()
+        .map(|s| StrategyListItem {
+            id: s.id,
+            name: s.name,
+            note: s.note,
+            options: serde_json::from_str(&s.options).unwrap_or_default(),
+            enabled: s.enabled,
+            modified_at: s.modified_at,
+            created_at: s.created_at,
+        })
+        .collect();
+
+    let total = items.len();
+    Ok(Json(json!({ "data": items, "total": total })))
+}
+
+async fn create_strategy(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<CreateStrategyRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let options = serde_json::to_string(&req.options)
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    let result = sqlx::query(
+        "INSERT INTO strategies (name, note, options, enabled, modified_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&req.name)
+    .bind(&req.note)
+    .bind(&options)
+    .bind(req.enabled)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db_err) if db_err.message().contains("UNIQUE") => {
+            ApiError::Conflict(format!("Strategy '{}' already exists", req.name), None)
+        }
+        _ => ApiError::Internal(e.to_string()),
+    })?;
+
+    Ok(Json(json!({ "id": result.last_insert_rowid() })))
+}
+
+async fn update_strategy(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+    Json(req): Json<Value>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    if let Some(name) = req.get("name").and_then(|v| v.as_str()) {
+        sqlx::query("UPDATE strategies SET name = ? WHERE id = ?")
+            .bind(name)
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+    }
+    if let Some(note) = req.get("note").and_then(|v| v.as_str()) {
+        sqlx::query("UPDATE strategies SET note = ? WHERE id = ?")
+            .bind(note)
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+    }
+
+    // Changes to what devices receive bump the version so clients pick them up
+    let mut modified = false;
+    if let Some(options) = req.get("options") {
+        let options: std::collections::HashMap<String, String> =
+            serde_json::from_value(options.clone()).map_err(|_| {
+                ApiError::BadRequest("Strategy options must map names to strings".to_string())
+            })?;
+        sqlx::query("UPDATE strategies SET options = ? WHERE id = ?")
+            .bind(serde_json::to_string(&options).map_err(|e| ApiError::Internal(e.to_string()))?)
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+        modified = true;
+    }
+    if let Some(enabled) = req.get("enabled").and_then(|v| v.as_bool()) {
+        sqlx::query("UPDATE strategies SET enabled = ? WHERE id = ?")
+            .bind(enabled)
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+        modified = true;
+    }
+    if modified {
+        sqlx::query("UPDATE strategies SET modified_at = ? WHERE id = ?")
+            .bind(chrono::Utc::now().timestamp())
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+    }
+
+    Ok(Json(json!({})))
+}
+
+async fn delete_strategy(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    sqlx::query("DELETE FROM strategy_assignments WHERE strategy_id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM strategies WHERE id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+
+    Ok(Json(json!({})))
+}
+
+async fn list_assignments(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let assignments = sqlx::query_as::<_, StrategyAssignment>(
+        "SELECT * FROM strategy_assignments WHERE strategy_id = ? ORDER BY id",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+
+    let total = assignments.len();
+    Ok(Json(json!({ "data": assignments, "total": total })))
+}
+
+/// POST /api/strategies/{id}/assignments — assign the strategy to one device,
+/// user or device group, replacing whatever strategy that target had before.
+async fn assign_strategy(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+    Json(req): Json<AssignStrategyRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    let (column, target_table, target_id) = match (req.device_id, req.user_id, req.device_group_id) {
+        (Some(device_id), None, None) => ("device_id", "devices", device_id),
+        (None, Some(user_id), None) => ("user_id", "users", user_id),
+        (None, None, Some(group_id)) => ("device_group_id", "device_groups", group_id),
+        _ => {
+            return Err(ApiError::BadRequest(
+                "Specify exactly one of device_id, user_id or device_group_id".to_string(),
+            ))
+        }
+    };
+
+    let strategy_exists: bool =
+        sqlx::query_scalar("SELECT COUNT(*) > 0 FROM strategies WHERE id = ?")
+            .bind(id)
+            .fetch_one(&state.db)
+            .await?;
+    if !strategy_exists {
+        return Err(ApiError::NotFound("Strategy not found".to_string()));
+    }
+
+    let target_exists: bool =
+        sqlx::query_scalar(&format!("SELECT COUNT(*) > 0 FROM {} WHERE id = ?", target_table))
+            .bind(target_id)
+            .fetch_one(&state.db)
+            .await?;
+    if !target_exists {
+        return Err(ApiError::NotFound("Assignment target not found".to_string()));
+    }
+
+    sqlx::query(&format!("DELETE FROM strategy_assignments WHERE {} = ?", column))
+        .bind(target_id)
+        .execute(&state.db)
+        .await?;
+    let result = sqlx::query(&format!(
+        "INSERT INTO strategy_assignments (strategy_id, {}, modified_at) VALUES (?, ?, ?)",
+        column
+    ))
+    .bind(id)
+    .bind(target_id)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db)
+    .await?;
+
+    Ok(Json(json!({ "id": result.last_insert_rowid() })))
+}
+
+async fn unassign_strategy(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&claims)?;
+
+    sqlx::query("DELETE FROM strategy_assignments WHERE id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+
+    Ok(Json(json!({})))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::test_support::TestApp;
+
+    async fn heartbeat(app: &TestApp, modified_at: i64) -> Value {
+        let report = json!({ "id": "100", "modified_at": modified_at });
+        app.call("POST", "/api/heartbeat", Some(report)).await.1
+    }
+
+    #[tokio::test]
+    async fn pushes_apply_once_and_removed_strategies_are_cleared() {
+        let app = TestApp::new().await;
+        let db = &app.state.db;
+        let device_id: i64 = sqlx::query_scalar(
+            "INSERT INTO devices (rustdesk_id, status, user_id) VALUES ('100', 'approved', 1) RETURNING id",
+        )
+        .fetch_one(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO strategies (id, name, options, modified_at) VALUES (1, 's', '{\"a\":\"1\"}', 1000)",
+        )
+        .execute(db)
+        .await
+        .unwrap();
+        let assignment: i64 = sqlx::query_scalar(
+            "INSERT INTO strategy_assignments (strategy_id, device_id, modified_at) VALUES (1, ?, 1000)
+             RETURNING id",
+        )
+        .bind(device_id)
+        .fetch_one(db)
+        .await
+        .unwrap();
+
+        let body = heartbeat(&app, 0).await;
+        assert_eq!(body["strategy"]["config_options"], json!({ "a": "1" }));
+        let at = body["modified_at"].as_i64().unwrap();
+        assert!(heartbeat(&app, at).await.get("strategy").is_none());
+
+        // A later edit to the user's address books moves modified_at on, but
+        // the strategy itself didn't change
+        sqlx::query("UPDATE address_books SET modified_at = ? WHERE guid = 'personal'")
+            .bind(at + 100)
+            .execute(db)
+            .await
+            .unwrap();
+        let body = heartbeat(&app, at).await;
+        assert!(body.get("strategy").is_none());
+        assert_eq!(body["modified_at"], at + 100);
+        let at = at + 100;
+
+        let push = json!({ "command": "strategy", "options": { "b": "2" } });
+        app.call("POST", &format!("/api/devices/{}/commands", device_id), Some(push))
+            .await;
+        let body = heartbeat(&app, at).await;
+        assert_eq!(body["strategy"]["config_options"], json!({ "a": "1", "b": "2" }));
+        let at = body["modified_at"].as_i64().unwrap();
+
+        // Acknowledged: a client that asks again only gets the assignment
+        assert!(heartbeat(&app, at).await.get("strategy").is_none());
+        let body = heartbeat(&app, 0).await;
+        assert_eq!(body["strategy"]["config_options"], json!({ "a": "1" }));
+        assert!(heartbeat(&app, at).await.get("strategy").is_none());
+
+        app.call("DELETE", &format!("/api/strategy-assignments/{}", assignment), None)
+            .await;
+        let body = heartbeat(&app, at).await;
+        assert_eq!(body["strategy"]["config_options"], json!({ "a": "" }));
+        let at = body["modified_at"].as_i64().unwrap();
+        assert!(heartbeat(&app, at).await.get("strategy").is_none());
+        let body = heartbeat(&app, 0).await;
+        assert_eq!(body["strategy"]["config_options"], json!({ "a": "" }));
+
+        // A push without an assignment behind it is kept once applied
+        let push = json!({ "command": "strategy", "options": { "c": "3" } });
+        app.call("POST", &format!("/api/devices/{}/commands", device_id), Some(push))
+            .await;
+        let body = heartbeat(&app, at).await;
+        assert_eq!(body["strategy"]["config_options"], json!({ "c": "3" }));
+        let at = body["modified_at"].as_i64().unwrap();
+        assert!(heartbeat(&app, at).await.get("strategy").is_none());
+        assert!(heartbeat(&app, at).await.get("strategy").is_none());
+    }
+}
@@ -1,8 +1,17 @@
-use axum::{extract::State, routing::post, Json, Router};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    routing::post,
+    Json, Router,
+};
 use serde_json::{json, Value};
 
+use crate::auth::middleware::AuthUser;
 use crate::error::ApiError;
 use crate::models::device::*;
+use crate::routes::changes::device_books_modified_at;
+use crate::routes::devices::{admit_device, claim_device, EnrollmentQuery};
+use crate::routes::strategies::device_strategy;
 use crate::state::AppState;
 
 pub fn routes() -> Router<AppState> {
@@ -15,51 +24,246 @@ pub fn routes() -> Router<AppState> {
 
 async fn heartbeat(
     State(state): State<AppState>,
+    user: Option<AuthUser>,
+    headers: HeaderMap,
+    Query(enrollment): Query<EnrollmentQuery>,
     Json(req): Json<HeartbeatRequest>,
 ) -> Result<Json<Value>, ApiError> {
-    if !req.id.is_empty() {
+    // The client expects a modified_at field back
+    let mut response = serde_json::Map::new();
+    response.insert("modified_at".to_string(), json!(""));
+
+    if req.id.is_empty() {
+        return Ok(Json(Value::Object(response)));
+    }
+
+    let Some((device_id, status)) =
+        admit_device(&state, &req.id, &req.uuid, &headers, &enrollment.token).await?
+    else {
+        return Ok(Json(Value::Object(response)));
+    };
+    if status == "rejected" {
+        return Ok(Json(Value::Object(response)));
+    }
+
+    if let Some(AuthUser(claims)) = user {
+        claim_device(&state.db, device_id, claims.user_id).await?;
+    }
+
+    sqlx::query(
+        "UPDATE devices SET
+             uuid = CASE WHEN ? = '' THEN uuid ELSE ? END,
+             online = TRUE,
+             last_online = CURRENT_TIMESTAMP
+         WHERE id = ?",
+    )
+    .bind(&req.uuid)
+    .bind(&req.uuid)
+    .bind(device_id)
+    .execute(&state.db)
+    .await?;
+
+    // Pending devices are tracked but receive nothing until approved
+    if status != "approved" {
+        return Ok(Json(Value::Object(response)));
+    }
+
+    acknowledge_commands(&state.db, device_id, &req).await?;
+    deliver_commands(&state.db, device_id, &mut response).await?;
+
+    // modified_at also covers the address books of the device's user, so
+    // clients notice when they should re-sync their books.
+    let (strategy_version, strategy) = device_strategy(&state.db, device_id, req.modified_at).await?;
+    if let Some(options) = strategy {
+        response.insert(
+            "strategy".to_string(),
+            json!({ "config_options": options, "extra": {} }),
+        );
+    }
+    let modified_at = strategy_version.max(device_books_modified_at(&state.db, device_id).await?);
+    if modified_at > 0 {
+        response.insert("modified_at".to_string(), json!(modified_at));
+    }
+
+    Ok(Json(Value::Object(response)))
+}
+
+/// Mark delivered commands as acknowledged once the heartbeat shows their effect:
+/// disconnected connections are no longer reported, and a pushed strategy is
+/// part of the strategy version the device reports having.
+async fn acknowledge_commands(
+    db: &sqlx::SqlitePool,
+    device_id: i64,
+    req: &HeartbeatRequest,
+) -> Result<(), ApiError> {
+    let delivered = sqlx::query_as::<_, DeviceCommand>(
+        "SELECT * FROM device_commands WHERE device_id = ? AND status = 'delivered'
+         AND command IN ('disconnect', 'strategy')",
+    )
+    .bind(device_id)
+    .fetch_all(db)
+    .await?;
+    if delivered.is_empty() {
+        return Ok(());
+    }
+
+    let (strategy_version, strategy_source): (i64, i64) =
+        sqlx::query_as("SELECT strategy_version, strategy_source FROM devices WHERE id = ?")
+            .bind(device_id)
+            .fetch_one(db)
+            .await?;
+
+    for cmd in delivered {
+        let payload: Value = serde_json::from_str(&cmd.payload).unwrap_or_default();
+        let done = match cmd.command.as_str() {
+            "disconnect" => payload["conns"]
+                .as_array()
+                .map(|conns| {
+                    conns
+                        .iter()
+                        .filter_map(|c| c.as_i64())
+                        .all(|c| !req.conns.contains(&(c as i32)))
+                })
+                .unwrap_or(true),
+            "strategy" => {
+                payload["modified_at"].as_i64().unwrap_or(0) <= strategy_source
+                    && strategy_version <= req.modified_at
+            }
+            _ => false,
+        };
+
+        if done {
+            sqlx::query(
+                "UPDATE device_commands SET status = 'acked', acked_at = CURRENT_TIMESTAMP WHERE id = ?",
+            )
+            .bind(cmd.id)
+            .execute(db)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add pending commands to the heartbeat response in the shape the client reads:
+/// `sysinfo` asks for a fresh sysinfo upload and `disconnect` lists connection
+/// IDs to close. Pushed strategies are delivered through `device_strategy`.
+async fn deliver_commands(
+    db: &sqlx::SqlitePool,
+    device_id: i64,
+    response: &mut serde_json::Map<String, Value>,
+) -> Result<(), ApiError> {
+    let pending = sqlx::query_as::<_, DeviceCommand>(
+        "SELECT * FROM device_commands WHERE device_id = ? AND status = 'pending' ORDER BY id",
+    )
+    .bind(device_id)
+    .fetch_all(db)
+    .await?;
+
+    let mut disconnect: Vec<i64> = Vec::new();
+    for cmd in &pending {
+        let payload: Value = serde_json::from_str(&cmd.payload).unwrap_or_default();
+        match cmd.command.as_str() {
+            "sysinfo" => {
+                response.insert("sysinfo".to_string(), json!(true));
+            }
+            "disconnect" => {
+                if let Some(conns) = payload["conns"].as_array() {
+                    disconnect.extend(conns.iter().filter_map(|c| c.as_i64()));
+                }
+            }
+            _ => {}
+        }
+
         sqlx::query(
-            "INSERT INTO devices (rustdesk_id, last_online)
-             VALUES (?, CURRENT_TIMESTAMP)
-             ON CONFLICT(rustdesk_id) DO UPDATE SET last_online = CURRENT_TIMESTAMP",
+            "UPDATE device_commands SET status = 'delivered', delivered_at = CURRENT_TIMESTAMP WHERE id = ?",
         )
-        .bind(&req.id)
-        .execute(&state.db)
+        .bind(cmd.id)
+        .execute(db)
         .await?;
     }
 
-    // The client expects a modified_at field back
-    Ok(Json(json!({ "modified_at": "" })))
+    if !disconnect.is_empty() {
+        response.insert("disconnect".to_string(), json!(disconnect));
+    }
+
+    Ok(())
 }
 
 async fn sysinfo(
     State(state): State<AppState>,
+    user: Option<AuthUser>,
+    headers: HeaderMap,
+    Query(enrollment): Query<EnrollmentQuery>,
     Json(req): Json<SysinfoRequest>,
 ) -> Result<Json<Value>, ApiError> {
-    if !req.id.is_empty() {
-        sqlx::query(
-            "INSERT INTO devices (rustdesk_id, hostname, platform, os, cpu, memory, version, last_online)
-             VALUES (?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
-             ON CONFLICT(rustdesk_id) DO UPDATE SET
-                 hostname = excluded.hostname,
-                 platform = excluded.platform,
-                 os = excluded.os,
-                 cpu = excluded.cpu,
-                 memory = excluded.memory,
-                 version = excluded.version,
-                 last_online = CURRENT_TIMESTAMP",
-        )
-        .bind(&req.id)
-        .bind(&req.hostname)
-        .bind(&req.platform)
-        .bind(&req.os)
-        .bind(&req.cpu)
-        .bind(&req.memory)
-        .bind(&req.version)
-        .execute(&state.db)
-        .await?;
+    if req.id.is_empty() {
+        return Ok(Json(json!({})));
+    }
+
+    // Pending devices may still report, so admins can see what they approve
+    let Some((device_id, status)) =
+        admit_device(&state, &req.id, &req.uuid, &headers, &enrollment.token).await?
+    else {
+        return Ok(Json(json!({})));
+    };
+    if status == "rejected" {
+        return Ok(Json(json!({})));
+    }
+
+    if let Some(AuthUser(claims)) = user {
+        claim_device(&state.db, device_id, claims.user_id).await?;
     }
 
+    sqlx::query(
+        "UPDATE devices SET
+             uuid = CASE WHEN ? = '' THEN uuid ELSE ? END,
+             hostname = ?,
+             platform = ?,
+             os = ?,
+             cpu = ?,
+             memory = ?,
+             version = ?,
+             online = TRUE,
+             last_online = CURRENT_TIMESTAMP
+         WHERE id = ?",
+    )
+    .bind(&req.uuid)
+    .bind(&req.uuid)
+    .bind(&req.hostname)
+    .bind(&req.platform)
+    .bind(&req.os)
+    .bind(&req.cpu)
+    .bind(&req.memory)
+    .bind(&req.version)
+    .bind(device_id)
+    .execute(&state.db)
+    .await?;
+
+    // Keep a change log: record the new values only if they differ from the last entry
+    sqlx::query(
+        "INSERT INTO device_sysinfo_history (device_id, hostname, os, cpu, memory, version)
+         SELECT d.id, d.hostname, d.os, d.cpu, d.memory, d.version FROM devices d
+         WHERE d.id = ? AND NOT EXISTS (
+             SELECT 1 FROM device_sysinfo_history h
+             WHERE h.id = (SELECT MAX(id) FROM device_sysinfo_history WHERE device_id = d.id)
+             AND h.hostname = d.hostname AND h.os = d.os AND h.cpu = d.cpu
+             AND h.memory = d.memory AND h.version = d.version
+         )",
+    )
+    .bind(device_id)
+    .execute(&state.db)
+    .await?;
+
+    // A fresh upload answers any sysinfo request sent with an earlier heartbeat
+    sqlx::query(
+        "UPDATE device_commands SET status = 'acked', acked_at = CURRENT_TIMESTAMP
+         WHERE device_id = ? AND command = 'sysinfo' AND status = 'delivered'",
+    )
+    .bind(device_id)
+    .execute(&state.db)
+    .await?;
+
     Ok(Json(json!({})))
 }
 
@@ -86,3 +290,54 @@ async fn audit(
 
     Ok(Json(json!({})))
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    use crate::auth::password::hash_password;
+
+    use crate::test_support::TestApp;
+
+    async fn device_owner(app: &TestApp, rustdesk_id: &str) -> Option<Option<i64>> {
+        sqlx::query_scalar("SELECT user_id FROM devices WHERE rustdesk_id = ?")
+            .bind(rustdesk_id)
+            .fetch_optional(&app.state.db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn only_admitted_devices_get_an_owner() {
+        let mut app = TestApp::new().await;
+        app.state.config.enrollment_token = "enroll".to_string();
+        let report = json!({ "id": "100", "uuid": "u1" });
+
+        // Logged in but without the enrollment token: not enrolled, not claimed
+        app.call("POST", "/api/heartbeat", Some(report.clone())).await;
+        app.call("POST", "/api/system/sysinfo", Some(report.clone())).await;
+        sqlx::query("UPDATE users SET password_hash = ? WHERE id = 1")
+            .bind(hash_password("secret").unwrap())
+            .execute(&app.state.db)
+            .await
+            .unwrap();
+        let login = json!({ "username": "admin", "password": "secret", "id": "100", "uuid": "u1" });
+        let (status, _) = app.call("POST", "/api/login", Some(login)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(device_owner(&app, "100").await, None);
+
+        app.call("POST", "/api/heartbeat?token=enroll", Some(report)).await;
+        assert_eq!(device_owner(&app, "100").await, Some(Some(1)));
+
+        // Rejected devices stay unowned
+        sqlx::query("INSERT INTO devices (rustdesk_id, status) VALUES ('200', 'rejected')")
+            .execute(&app.state.db)
+            .await
+            .unwrap();
+        let report = json!({ "id": "200", "uuid": "u2" });
+        app.call("POST", "/api/heartbeat", Some(report.clone())).await;
+        app.call("POST", "/api/system/sysinfo", Some(report)).await;
+        assert_eq!(device_owner(&app, "200").await, Some(None));
+    }
+}
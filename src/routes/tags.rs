@@ -1,13 +1,19 @@
 use axum::{
     extract::{Path, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
     routing::{delete, get, post, put},
     Json, Router,
 };
 use serde_json::{json, Value};
+use sqlx::SqliteConnection;
 
 use crate::auth::middleware::AuthUser;
 use crate::error::ApiError;
 use crate::models::tag::*;
+use crate::routes::changes::{
+    book_etag, book_revision, check_if_match, not_modified, BookChanges,
+};
 use crate::routes::peers::resolve_ab_guid;
 use crate::state::AppState;
 
@@ -20,46 +26,102 @@ pub fn routes() -> Router<AppState> {
         .route("/api/ab/tag/{guid}", delete(delete_tags))
 }
 
+/// Load a tag and check it against the version the client last saw.
+/// A mismatch is a `Conflict` carrying the tag as it is now.
+async fn check_tag_version(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+    name: &str,
+    expected: Option<i64>,
+) -> Result<Option<Tag>, ApiError> {
+    let tag = sqlx::query_as::<_, Tag>("SELECT * FROM tags WHERE ab_guid = ? AND name = ?")
+        .bind(ab_guid)
+        .bind(name)
+        .fetch_optional(conn)
+        .await?;
+
+    let Some(expected) = expected else {
+        return Ok(tag);
+    };
+    match tag {
+        Some(tag) if tag.version == expected => Ok(Some(tag)),
+        Some(tag) => Err(ApiError::Conflict(
+            format!("Tag '{}' has been modified", name),
+            Some(json!(TagPayload {
+                name: tag.name,
+                color: tag.color,
+                version: tag.version,
+            })),
+        )),
+        None => Err(ApiError::Conflict(
+            format!("Tag '{}' has been deleted", name),
+            None,
+        )),
+    }
+}
+
 async fn get_tags(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
+    headers: HeaderMap,
     Path(guid): Path<String>,
-) -> Result<Json<TagsResponse>, ApiError> {
+) -> Result<Response, ApiError> {
     let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
 
+    let mut tx = state.db.begin().await?;
+
+    let etag = book_etag(&guid, book_revision(&mut tx, &guid).await?);
+    if let Some(response) = not_modified(&headers, &etag) {
+        return Ok(response);
+    }
+
     let tags = sqlx::query_as::<_, Tag>(
         "SELECT * FROM tags WHERE ab_guid = ? ORDER BY name",
     )
     .bind(&guid)
-    .fetch_all(&state.db)
+    .fetch_all(&mut *tx)
     .await?;
 
+    tx.commit().await?;
+
     let total = tags.len() as i64;
     let data: Vec<TagPayload> = tags
         .into_iter()
         .map(|t| TagPayload {
             name: t.name,
             color: t.color,
+            version: t.version,
         })
         .collect();
 
-    Ok(Json(TagsResponse { data, total }))
+    Ok(([(header::ETAG, etag)], Json(TagsResponse { data, total })).into_response())
 }
 
 async fn add_tag(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
+    headers: HeaderMap,
     Path(guid): Path<String>,
     Json(req): Json<AddTagRequest>,
 ) -> Result<Json<Value>, ApiError> {
     let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
 
-    sqlx::query("INSERT OR IGNORE INTO tags (ab_guid, name, color) VALUES (?, ?, ?)")
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+
+    let result = sqlx::query("INSERT OR IGNORE INTO tags (ab_guid, name, color) VALUES (?, ?, ?)")
         .bind(&guid)
         .bind(&req.name)
         .bind(req.color)
-        .execute(&state.db)
+        .execute(&mut *tx)
         .await?;
+    if result.rows_affected() > 0 {
+        BookChanges::new(&guid, claims.user_id, "tag_add")
+            .touch_tag(&mut tx, result.last_insert_rowid())
+            .await?;
+    }
+
+    tx.commit().await?;
 
     Ok(Json(json!({})))
 }
@@ -67,17 +129,31 @@ async fn add_tag(
 async fn rename_tag(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
+    headers: HeaderMap,
     Path(guid): Path<String>,
     Json(req): Json<RenameTagRequest>,
 ) -> Result<Json<Value>, ApiError> {
     let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
 
-    sqlx::query("UPDATE tags SET name = ? WHERE ab_guid = ? AND name = ?")
-        .bind(&req.new)
-        .bind(&guid)
-        .bind(&req.old)
-        .execute(&state.db)
-        .await?;
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+    check_tag_version(&mut tx, &guid, &req.old, req.version).await?;
+
+    let tag_id: Option<i64> =
+        sqlx::query_scalar("UPDATE tags SET name = ? WHERE ab_guid = ? AND name = ? RETURNING id")
+            .bind(&req.new)
+            .bind(&guid)
+            .bind(&req.old)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+    if let Some(tag_id) = tag_id {
+        BookChanges::new(&guid, claims.user_id, "tag_rename")
+            .rename_tag(&mut tx, tag_id, &req.old)
+            .await?;
+    }
+
+    tx.commit().await?;
 
     Ok(Json(json!({})))
 }
@@ -85,17 +161,30 @@ async fn rename_tag(
 async fn update_tag_color(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
+    headers: HeaderMap,
     Path(guid): Path<String>,
     Json(req): Json<UpdateTagColorRequest>,
 ) -> Result<Json<Value>, ApiError> {
     let guid = resolve_ab_guid(&state.db, claims.user_id, &guid).await?;
 
-    sqlx::query("UPDATE tags SET color = ? WHERE ab_guid = ? AND name = ?")
-        .bind(req.color)
-        .bind(&guid)
-        .bind(&req.name)
-        .execute(&state.db)
-        .await?;
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+    check_tag_version(&mut tx, &guid, &req.name, req.version).await?;
+
+    let tag_id: Option<i64> =
+        sqlx::query_scalar("UPDATE tags SET color = ? WHERE ab_guid = ? AND name = ? RETURNING id")
+            .bind(req.color)
+            .bind(&guid)
+            .bind(&req.name)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if let Some(tag_id) = tag_id {
+        BookChanges::new(&guid, claims.user_id, "tag_update")
+            .touch_tag(&mut tx, tag_id)
+            .await?;
+    }
+
+    tx.commit().await?;
 
     Ok(Json(json!({})))
 }
@@ -103,6 +192,7 @@ async fn update_tag_color(
 async fn delete_tags(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
+    headers: HeaderMap,
     Path(guid): Path<String>,
     Json(req): Json<DeleteTagRequest>,
 ) -> Result<Json<Value>, ApiError> {
@@ -113,22 +203,27 @@ async fn delete_tags(
         names.push(name);
     }
 
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+    let mut changes = BookChanges::new(&guid, claims.user_id, "tag_delete");
+
     for name in &names {
+        let expected = req.versions.get(name).copied();
+        let Some(tag) = check_tag_version(&mut tx, &guid, name, expected).await? else {
+            continue;
+        };
+
         // Remove peer_tags associations first
-        sqlx::query(
-            "DELETE FROM peer_tags WHERE tag_id IN (SELECT id FROM tags WHERE ab_guid = ? AND name = ?)",
-        )
-        .bind(&guid)
-        .bind(name)
-        .execute(&state.db)
-        .await?;
+        changes.untag_peers(&mut tx, tag.id).await?;
 
-        sqlx::query("DELETE FROM tags WHERE ab_guid = ? AND name = ?")
-            .bind(&guid)
-            .bind(name)
-            .execute(&state.db)
+        sqlx::query("DELETE FROM tags WHERE id = ?")
+            .bind(tag.id)
+            .execute(&mut *tx)
             .await?;
+        changes.delete_tag(&mut tx, &tag).await?;
     }
 
+    tx.commit().await?;
+
     Ok(Json(json!({})))
 }
@@ -0,0 +1,195 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use sqlx::SqliteConnection;
+
+use crate::auth::middleware::AuthUser;
+use crate::error::ApiError;
+use crate::models::peer::*;
+use crate::routes::changes::{check_if_match, BookChanges};
+use crate::routes::peers::{require_full_control, tags_for_peers, PeerQuota};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/ab/trash/{guid}", get(list_trash).delete(purge_trash))
+        .route("/api/ab/trash/{guid}/restore", post(restore_trash))
+}
+
+/// Permanently delete the given peers together with their tag associations.
+pub async fn purge_peers(conn: &mut SqliteConnection, peer_ids: &[i64]) -> Result<u64, sqlx::Error> {
+    if peer_ids.is_empty() {
+        return Ok(0);
+    }
+    let ids = serde_json::to_string(peer_ids).unwrap_or_default();
+
+    sqlx::query("DELETE FROM peer_tags WHERE peer_id IN (SELECT value FROM json_each(?))")
+        .bind(&ids)
+        .execute(&mut *conn)
+        .await?;
+    let result = sqlx::query("DELETE FROM peers WHERE id IN (SELECT value FROM json_each(?))")
+        .bind(&ids)
+        .execute(conn)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Row ids of the trashed peers a restore or purge request applies to.
+async fn selected_peers(
+    conn: &mut SqliteConnection,
+    ab_guid: &str,
+    req: &TrashRequest,
+) -> Result<Vec<i64>, ApiError> {
+    let ids = serde_json::to_string(&req.ids).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let peer_ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM peers WHERE ab_guid = ? AND deleted_at IS NOT NULL
+         AND (? OR rustdesk_id IN (SELECT value FROM json_each(?)))",
+    )
+    .bind(ab_guid)
+    .bind(req.all)
+    .bind(ids)
+    .fetch_all(conn)
+    .await?;
+    Ok(peer_ids)
+}
+
+/// GET /api/ab/trash/{guid} — peers deleted from the book, most recent first.
+async fn list_trash(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(guid): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_full_control(&state.db, claims.user_id, &guid).await?;
+
+    let mut tx = state.db.begin().await?;
+
+    let peers = sqlx::query_as::<_, Peer>(
+        "SELECT * FROM peers WHERE ab_guid = ? AND deleted_at IS NOT NULL
+         ORDER BY deleted_at DESC, rustdesk_id",
+    )
+    .bind(&guid)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let peer_ids: Vec<i64> = peers.iter().map(|p| p.id).collect();
+    let mut tags = tags_for_peers(&mut tx, &peer_ids).await?;
+
+    tx.commit().await?;
+
+    let items: Vec<TrashedPeerPayload> = peers
+        .into_iter()
+        .map(|peer| TrashedPeerPayload {
+            peer: PeerPayload::new(&peer, tags.remove(&peer.id).unwrap_or_default()),
+            deleted_at: peer.deleted_at.unwrap_or_default(),
+        })
+        .collect();
+
+    let total = items.len();
+    Ok(Json(json!({ "data": items, "total": total })))
+}
+
+/// POST /api/ab/trash/{guid}/restore — move peers back into the book.
+async fn restore_trash(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    headers: HeaderMap,
+    Path(guid): Path<String>,
+    Json(req): Json<TrashRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_full_control(&state.db, claims.user_id, &guid).await?;
+
+    let mut tx = state.db.begin().await?;
+    check_if_match(&mut tx, &headers, &guid).await?;
+    let quota = PeerQuota::load(&mut tx, &state.config, &guid).await?;
+    let mut changes = BookChanges::new(&guid, claims.user_id, "trash_restore");
+
+    let peer_ids = selected_peers(&mut tx, &guid, &req).await?;
+    for peer_id in &peer_ids {
+        sqlx::query("UPDATE peers SET deleted_at = NULL, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(peer_id)
+            .execute(&mut *tx)
+            .await?;
+        changes.touch_peer(&mut tx, *peer_id).await?;
+    }
+
+    quota.check(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({ "restored": peer_ids.len() })))
+}
+
+/// DELETE /api/ab/trash/{guid} — permanently delete trashed peers.
+async fn purge_trash(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(guid): Path<String>,
+    Json(req): Json<TrashRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_full_control(&state.db, claims.user_id, &guid).await?;
+
+    let mut tx = state.db.begin().await?;
+    let peer_ids = selected_peers(&mut tx, &guid, &req).await?;
+    let purged = purge_peers(&mut tx, &peer_ids).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({ "purged": purged })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    #[tokio::test]
+    async fn trashed_peers_can_be_restored_or_purged() {
+        let app = TestApp::new().await;
+        app.call("POST", "/api/ab/tag/add/personal", Some(json!({ "name": "web" }))).await;
+        for id in ["100", "200"] {
+            let peer = json!({ "id": id, "tags": ["web"] });
+            app.call("POST", "/api/ab/peer/add/personal", Some(peer)).await;
+        }
+        let delete = json!({ "ids": ["100", "200"] });
+        app.call("DELETE", "/api/ab/peer/personal", Some(delete)).await;
+
+        let (status, body) = app.call("GET", "/api/ab/trash/personal", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 2);
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(body["total"], 0);
+
+        // Only full control gives access to the trash
+        let reader = app.add_user(2, "reader").await;
+        app.share(2, 2).await;
+        let response = app.send_as(&reader, "GET", "/api/ab/trash/personal", &[], None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let restore = json!({ "ids": ["100"] });
+        let (status, body) = app.call("POST", "/api/ab/trash/personal/restore", Some(restore)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["restored"], 1);
+        let (_, body) = app.call("GET", "/api/ab/peers?ab=personal", None).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["data"][0]["id"], "100");
+        assert_eq!(body["data"][0]["tags"], json!(["web"]));
+
+        let (status, body) = app
+            .call("DELETE", "/api/ab/trash/personal", Some(json!({ "all": true })))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["purged"], 1);
+        let (_, body) = app.call("GET", "/api/ab/trash/personal", None).await;
+        assert_eq!(body["total"], 0);
+        let left: Vec<String> = sqlx::query_scalar("SELECT rustdesk_id FROM peers")
+            .fetch_all(&app.state.db)
+            .await
+            .unwrap();
+        assert_eq!(left, ["100"]);
+    }
+}
@@ -73,7 +73,7 @@ async fn create_user(
     .await
     .map_err(|e| match e {
         sqlx::Error::Database(ref db_err) if db_err.message().contains("UNIQUE") => {
-            ApiError::Conflict(format!("Username '{}' already exists", req.username))
+            ApiError::Conflict(format!("Username '{}' already exists", req.username), None)
         }
         _ => ApiError::Internal(e.to_string()),
     })?;
@@ -1,3 +1,4 @@
+use crate::auth::peer_password::PeerPasswordKeys;
 use crate::config::Config;
 use sqlx::sqlite::SqlitePool;
 
@@ -5,4 +6,5 @@ use sqlx::sqlite::SqlitePool;
 pub struct AppState {
     pub db: SqlitePool,
     pub config: Config,
+    pub peer_keys: PeerPasswordKeys,
 }
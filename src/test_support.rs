@@ -0,0 +1,218 @@
+//! Shared setup for route tests: an in-memory database with every migration
+//! applied, an admin user owning the book `personal`, and a router to send
+//! requests through.
+//!
+//! Each test database runs on a worker thread of its own name, so the SQL
+//! statements it executes can be counted from sqlx's `sqlx::query` events.
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+use tower::ServiceExt;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+
+use crate::auth::jwt::create_token;
+use crate::auth::peer_password::PeerPasswordKeys;
+use crate::config::Config;
+use crate::state::AppState;
+
+pub const BOOK: &str = "personal";
+
+/// Statements executed so far, by database worker thread name.
+static QUERIES: Mutex<Option<HashMap<String, usize>>> = Mutex::new(None);
+
+struct QueryCounter;
+
+impl<S: tracing::Subscriber> Layer<S> for QueryCounter {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != "sqlx::query" {
+            return;
+        }
+        if let Some(name) = std::thread::current().name() {
+            let mut queries = QUERIES.lock().unwrap();
+            *queries.get_or_insert_with(HashMap::new).entry(name.to_string()).or_default() += 1;
+        }
+    }
+}
+
+pub struct TestApp {
+    pub state: AppState,
+    pub token: String,
+    db_thread: String,
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        static COUNTER: Once = Once::new();
+        COUNTER.call_once(|| {
+            tracing::subscriber::set_global_default(tracing_subscriber::registry().with(QueryCounter))
+                .expect("Failed to install the query counter");
+        });
+        static NEXT_DB: AtomicUsize = AtomicUsize::new(0);
+        let db_thread = format!("test-db-{}", NEXT_DB.fetch_add(1, Ordering::Relaxed));
+
+        // A single connection that never closes keeps the in-memory database alive
+        let thread_name = db_thread.clone();
+        let options = "sqlite::memory:"
+            .parse::<SqliteConnectOptions>()
+            .unwrap()
+            .thread_name(move |_| thread_name.clone());
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await
+            .expect("Failed to open in-memory database");
+        sqlx::query("PRAGMA foreign_keys=ON")
+            .execute(&pool)
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await;
+
+        sqlx::query(
+            "INSERT INTO users (id, username, password_hash, is_admin) VALUES (1, 'admin', '', TRUE)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO address_books (guid, name, owner_id, is_personal) VALUES (?, 'Personal', 1, TRUE)",
+        )
+        .bind(BOOK)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let config: Config = toml::from_str(
+            "jwt_secret = \"test-secret\"\npeer_password_keys = [\"k1:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=\"]",
+        )
+        .unwrap();
+        let token = create_token("admin", 1, true, &config.jwt_secret, 1).unwrap();
+        let peer_keys = PeerPasswordKeys::new(&config.peer_password_keys).unwrap();
+
+        TestApp {
+            state: AppState {
+                db: pool,
+                config,
+                peer_keys,
+            },
+            token,
+            db_thread,
+        }
+    }
+
+    /// Number of SQL statements this app's database has executed so far.
+    pub fn queries_run(&self) -> usize {
+        let queries = QUERIES.lock().unwrap();
+        queries
+            .as_ref()
+            .and_then(|queries| queries.get(&self.db_thread).copied())
+            .unwrap_or(0)
+    }
+
+    /// Create a regular user and return a token for them.
+    pub async fn add_user(&self, id: i64, username: &str) -> String {
+        sqlx::query("INSERT INTO users (id, username, password_hash) VALUES (?, ?, '')")
+            .bind(id)
+            .bind(username)
+            .execute(&self.state.db)
+            .await
+            .unwrap();
+        create_token(username, id, false, &self.state.config.jwt_secret, 1).unwrap()
+    }
+
+    /// Add a shared (non-personal) address book.
+    pub async fn add_book(&self, guid: &str, owner_id: i64) {
+        sqlx::query("INSERT INTO address_books (guid, name, owner_id) VALUES (?, ?, ?)")
+            .bind(guid)
+            .bind(guid)
+            .bind(owner_id)
+            .execute(&self.state.db)
+            .await
+            .unwrap();
+    }
+
+    /// Share the test book with a user under the given rule.
+    pub async fn share(&self, user_id: i64, rule: i32) {
+        sqlx::query("INSERT INTO ab_shares (ab_guid, user_id, rule) VALUES (?, ?, ?)")
+            .bind(BOOK)
+            .bind(user_id)
+            .bind(rule)
+            .execute(&self.state.db)
+            .await
+            .unwrap();
+    }
+
+    fn router(&self) -> Router {
+        crate::routes::api_router().with_state(self.state.clone())
+    }
+
+    /// Send a request as the admin, returning the raw response.
+    pub async fn send(
+        &self,
+        method: &str,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: Option<String>,
+    ) -> Response {
+        self.send_as(&self.token, method, uri, headers, body).await
+    }
+
+    /// Send a request as the holder of `token`, returning the raw response.
+    pub async fn send_as(
+        &self,
+        token: &str,
+        method: &str,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: Option<String>,
+    ) -> Response {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let body = match body {
+            Some(body) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                Body::from(body)
+            }
+            None => Body::empty(),
+        };
+        self.router()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap()
+    }
+
+    /// Send a JSON request as the admin and read the JSON response.
+    pub async fn call(&self, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let response = self
+            .send(method, uri, &[], body.map(|b| b.to_string()))
+            .await;
+        read_json(response).await
+    }
+}
+
+pub async fn read_json(response: Response) -> (StatusCode, Value) {
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let value = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap()
+    };
+    (status, value)
+}